@@ -0,0 +1,1291 @@
+use crate::feeds::{Enclosure, Feed, FeedEntry, FeedKind, Validators};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io, path::Path, time::Duration};
+
+/// Summary of what the cache holds
+#[derive(Debug)]
+pub struct CacheStats {
+  pub feeds: usize,
+  pub entries: usize,
+  pub unread: usize,
+  /// Size of the database file in bytes
+  pub size: u64,
+}
+
+/// How fetching a stored feed has gone over the refreshes that reached the cache
+#[derive(Debug, Clone, Default)]
+pub struct FeedHealth {
+  /// Fetches that failed since the last one that didn't
+  pub consecutive_failures: u32,
+  /// Last fetch that returned the feed or found it unchanged
+  pub last_success: Option<DateTime<Utc>>,
+  /// Entries per fetch that returned the feed, `None` before the first
+  pub average_entries: Option<f64>,
+}
+
+/// What `--export-json` writes and `--import-json` reads. Fields are only ever added,
+/// so scripts can rely on the ones here; `version` goes up if one has to change meaning.
+#[derive(Serialize, Deserialize)]
+struct JsonExport {
+  version: u32,
+  /// In the order of urls.toml when they were last fetched
+  feeds: Vec<JsonFeed>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonFeed {
+  url: String,
+  title: String,
+  /// RFC 3339, null if it was never fetched
+  last_fetched: Option<String>,
+  #[serde(default)]
+  entries: Vec<JsonEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonEntry {
+  title: String,
+  /// As the feed gave it, null if it had no date
+  published: Option<String>,
+  author: Option<String>,
+  #[serde(default)]
+  links: Vec<String>,
+  #[serde(default)]
+  media: Vec<JsonMedia>,
+  #[serde(default)]
+  read: bool,
+  #[serde(default)]
+  starred: bool,
+  /// Plain text of the content or summary, null if the feed had none
+  text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonMedia {
+  url: String,
+  /// MIME type, null if the feed didn't give one
+  #[serde(rename = "type")]
+  mime_type: Option<String>,
+}
+
+/// Schema changes in order, a database's `user_version` is how many of them it has had.
+/// Changes go in a new migration at the end, those already released never change.
+const MIGRATIONS: &[&str] = &[
+  // 1: feeds, their entries and state kept between launches
+  "CREATE TABLE feeds (
+    id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    last_fetched INTEGER
+  );
+  CREATE TABLE entries (
+    id INTEGER PRIMARY KEY,
+    feed_id INTEGER NOT NULL REFERENCES feeds(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    published TEXT NOT NULL DEFAULT '',
+    plain_text TEXT NOT NULL,
+    links TEXT NOT NULL,
+    media TEXT NOT NULL,
+    read INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (feed_id, title, published)
+  );
+  CREATE TABLE app_state (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+  );",
+  // 2: validators for conditional requests
+  "ALTER TABLE feeds ADD COLUMN etag TEXT;
+  ALTER TABLE feeds ADD COLUMN last_modified TEXT;",
+  // 3: entries starred with f
+  "ALTER TABLE entries ADD COLUMN starred INTEGER NOT NULL DEFAULT 0;",
+  // 4: when the entry was last part of a fetched feed, NULL for entries stored before
+  "ALTER TABLE entries ADD COLUMN seen_at INTEGER;",
+  // 5: author of the entry, NULL if the feed gave none
+  "ALTER TABLE entries ADD COLUMN author TEXT;",
+  // 6: furthest line of the laid out text the entry was scrolled to
+  "ALTER TABLE entries ADD COLUMN scroll_position INTEGER NOT NULL DEFAULT 0;",
+  // 7: fetch statistics for `feed_health`
+  "ALTER TABLE feeds ADD COLUMN consecutive_failures INTEGER NOT NULL DEFAULT 0;
+  ALTER TABLE feeds ADD COLUMN last_success INTEGER;
+  ALTER TABLE feeds ADD COLUMN fetch_count INTEGER NOT NULL DEFAULT 0;
+  ALTER TABLE feeds ADD COLUMN fetched_entries INTEGER NOT NULL DEFAULT 0;",
+  // 8: entries of feeds deleted before foreign keys were enforced
+  "DELETE FROM entries WHERE feed_id NOT IN (SELECT id FROM feeds);",
+];
+
+/// How long a write waits for another connection to finish its own
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SQLite backed storage for fetched feeds and the read state of their entries.
+///
+/// The connection isn't `Sync`, so a cache belongs to the task that opened it: in the
+/// interface that's the main task, and fetching tasks send what they fetched over the
+/// update channel for it to save instead of writing themselves. Other processes, like
+/// `--refresh` from cron while the interface is open, have their own connection. The
+/// database is in WAL mode so they can read while one of them writes, a writer waits
+/// for the other as long as the busy timeout.
+#[derive(Debug)]
+pub struct FeedCache {
+  conn: Connection,
+}
+
+impl FeedCache {
+  pub fn new(path: &Path) -> Result<Self> {
+    FeedCache::with_connection(Connection::open(path)?)
+  }
+
+  fn with_connection(conn: Connection) -> Result<Self> {
+    // Stays set in the file, in memory databases keep their own journal
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    // SQLite leaves `ON DELETE CASCADE` unenforced unless it's turned on per connection
+    conn.pragma_update(None, "foreign_keys", true)?;
+    // With WAL a crash can only lose the last commits, not corrupt the file
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    let cache = FeedCache { conn };
+    cache.init_schema()?;
+    Ok(cache)
+  }
+
+  /// Brings the schema up to date by running the migrations the database hasn't had yet
+  fn init_schema(&self) -> Result<()> {
+    let version: usize = self
+      .conn
+      .pragma_query_value(None, "user_version", |row| row.get(0))?;
+    // A database of a newer version keeps its columns, they're left alone
+    for (number, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+      let transaction = self.conn.unchecked_transaction()?;
+      transaction.execute_batch(migration)?;
+      transaction.pragma_update(None, "user_version", number + 1)?;
+      transaction.commit()?;
+    }
+    Ok(())
+  }
+
+  /// Upserts a feed and its entries in one transaction, keeping the read state of entries
+  /// already stored. Returns the entries that weren't stored yet, none for a feed that
+  /// wasn't either, since all of them would be.
+  pub fn save_feed<'a>(&self, feed: &'a Feed, position: usize) -> Result<Vec<&'a FeedEntry>> {
+    let transaction = self.conn.unchecked_transaction()?;
+    let inserted = upsert_feed(&transaction, feed, position)?;
+    transaction.commit()?;
+    Ok(inserted)
+  }
+
+  /// Upserts the feeds of a refresh at their `position` in one transaction and counts
+  /// them as fetched, returning the entries they brought in like `save_feed`
+  pub fn save_all_feeds<'a>(&mut self, feeds: &'a [Feed]) -> Result<Vec<&'a FeedEntry>> {
+    let transaction = self.conn.transaction()?;
+    let mut inserted = Vec::new();
+    for feed in feeds {
+      inserted.extend(upsert_feed(&transaction, feed, feed.position)?);
+      record_success(&transaction, &feed.url, Some(feed.entries.len()))?;
+    }
+    transaction.commit()?;
+    Ok(inserted)
+  }
+
+  /// Counts a fetch that found the feed unchanged as a success
+  pub fn record_not_modified(&self, url: &str) -> Result<()> {
+    record_success(&self.conn, url, None)
+  }
+
+  /// Counts a failed fetch of the feed, which is only tracked once it's stored
+  pub fn record_failure(&self, url: &str) -> Result<()> {
+    self.conn.execute(
+      "UPDATE feeds SET consecutive_failures = consecutive_failures + 1 WHERE url = ?1",
+      params![url],
+    )?;
+    Ok(())
+  }
+
+  /// How fetching each stored feed has gone, by URL
+  pub fn feed_health(&self) -> Result<HashMap<String, FeedHealth>> {
+    let mut stmt = self.conn.prepare(
+      "SELECT url, consecutive_failures, last_success, fetch_count, fetched_entries FROM feeds",
+    )?;
+    let rows = stmt.query_map([], |row| {
+      let fetch_count: u32 = row.get(3)?;
+      let fetched_entries: u64 = row.get(4)?;
+      Ok((
+        row.get::<_, String>(0)?,
+        FeedHealth {
+          consecutive_failures: row.get(1)?,
+          last_success: row
+            .get::<_, Option<i64>>(2)?
+            .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+          average_entries: (fetch_count > 0)
+            .then(|| fetched_entries as f64 / f64::from(fetch_count)),
+        },
+      ))
+    })?;
+    rows.collect()
+  }
+
+  /// Stores the order of feeds after they were moved, as (url, position) pairs
+  pub fn update_positions(&mut self, positions: &[(&str, usize)]) -> Result<()> {
+    let transaction = self.conn.transaction()?;
+    for (url, position) in positions {
+      transaction.execute(
+        "UPDATE feeds SET position = ?2 WHERE url = ?1",
+        params![url, position],
+      )?;
+    }
+    transaction.commit()
+  }
+
+  /// Loads a stored feed with all its entries, leaving out their text for `load_texts`
+  /// or `load_entry_body` to fill in when needed. Tags, the muted flag and the display
+  /// age limit live in the config and are left for the caller to fill in.
+  pub fn load_feed(&self, url: &str) -> Result<Option<Feed>> {
+    let Some((feed_id, title, position, etag, last_modified)) = self
+      .conn
+      .query_row(
+        "SELECT id, title, position, etag, last_modified FROM feeds WHERE url = ?1",
+        params![url],
+        |row| {
+          Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, usize>(2)?,
+            row.get(3)?,
+            row.get(4)?,
+          ))
+        },
+      )
+      .optional()?
+    else {
+      return Ok(None);
+    };
+
+    let mut stmt = self.conn.prepare(
+      "SELECT title, published, links, media, read, starred, author
+       FROM entries WHERE feed_id = ?1 ORDER BY id",
+    )?;
+    let entries = stmt
+      .query_map(params![feed_id], |row| {
+        let published: String = row.get(1)?;
+        let links: String = row.get(2)?;
+        Ok(FeedEntry {
+          title: row.get(0)?,
+          published_at: published.parse::<DateTime<Utc>>().ok(),
+          published: (!published.is_empty()).then_some(published),
+          author: row.get(6)?,
+          plain_text: None,
+          links: links.lines().map(str::to_string).collect(),
+          enclosures: decode_enclosures(&row.get::<_, String>(3)?),
+          read: row.get(4)?,
+          starred: row.get(5)?,
+          feed_url: url.to_string(),
+        })
+      })?
+      .collect::<Result<Vec<_>>>()?;
+
+    Ok(Some(Feed {
+      url: url.to_string(),
+      title,
+      entries,
+      tags: None,
+      muted: false,
+      position,
+      validators: Validators {
+        etag,
+        last_modified,
+      },
+      display_max_age_days: None,
+      kind: FeedKind::Subscription,
+    }))
+  }
+
+  /// Fills in the text of the entries of `feed` that were loaded without it
+  pub fn load_texts(&self, feed: &mut Feed) -> Result<()> {
+    let mut stmt = self.conn.prepare_cached(
+      "SELECT e.plain_text FROM entries e JOIN feeds f ON e.feed_id = f.id
+       WHERE f.url = ?1 AND e.title = ?2 AND e.published = ?3",
+    )?;
+    for entry in feed.entries.iter_mut().filter(|e| e.plain_text.is_none()) {
+      let published = entry.published.as_deref().unwrap_or_default();
+      entry.plain_text = stmt
+        .query_row(params![entry.feed_url, entry.title, published], |row| {
+          row.get(0)
+        })
+        .optional()?;
+    }
+    Ok(())
+  }
+
+  /// Text of a single stored entry
+  pub fn load_entry_body(
+    &self,
+    feed_url: &str,
+    title: &str,
+    published: Option<&str>,
+  ) -> Result<Option<String>> {
+    self
+      .conn
+      .query_row(
+        "SELECT e.plain_text FROM entries e JOIN feeds f ON e.feed_id = f.id
+         WHERE f.url = ?1 AND e.title = ?2 AND e.published = ?3",
+        params![feed_url, title, published.unwrap_or_default()],
+        |row| row.get(0),
+      )
+      .optional()
+  }
+
+  /// Line the entry was last read up to, 0 for entries never scrolled
+  pub fn load_scroll_position(
+    &self,
+    feed_url: &str,
+    title: &str,
+    published: Option<&str>,
+  ) -> Result<usize> {
+    let position = self
+      .conn
+      .query_row(
+        "SELECT e.scroll_position FROM entries e JOIN feeds f ON e.feed_id = f.id
+         WHERE f.url = ?1 AND e.title = ?2 AND e.published = ?3",
+        params![feed_url, title, published.unwrap_or_default()],
+        |row| row.get(0),
+      )
+      .optional()?;
+    Ok(position.unwrap_or(0))
+  }
+
+  /// Stores how far the entry was read, keeping an earlier position that went further
+  pub fn save_scroll_position(
+    &self,
+    feed_url: &str,
+    title: &str,
+    published: Option<&str>,
+    position: usize,
+  ) -> Result<()> {
+    self.conn.execute(
+      "UPDATE entries SET scroll_position = MAX(scroll_position, ?4)
+       WHERE feed_id = (SELECT id FROM feeds WHERE url = ?1) AND title = ?2 AND published = ?3",
+      params![feed_url, title, published.unwrap_or_default(), position],
+    )?;
+    Ok(())
+  }
+
+  /// Writes every stored feed and entry as JSON, see `JsonExport` for the format.
+  /// Returns how many feeds and entries were written.
+  pub fn export_json(&self, mut writer: impl io::Write) -> io::Result<(usize, usize)> {
+    let export = self.json_export().map_err(io::Error::other)?;
+    let entries = export.feeds.iter().map(|f| f.entries.len()).sum();
+    serde_json::to_writer_pretty(&mut writer, &export)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok((export.feeds.len(), entries))
+  }
+
+  /// Stores the feeds and entries of a JSON export with the read and starred state it
+  /// has, so importing the same file again changes nothing. Feeds keep their title and
+  /// position if they're stored already. Returns how many feeds and entries were read.
+  pub fn import_json(&mut self, reader: impl io::Read) -> io::Result<(usize, usize)> {
+    let export: JsonExport = serde_json::from_reader(reader)?;
+    if export.version != 1 {
+      return Err(io::Error::other(format!(
+        "Unsupported export version {}, expected 1",
+        export.version
+      )));
+    }
+    let entries = export.feeds.iter().map(|f| f.entries.len()).sum();
+    self.store_json(&export).map_err(io::Error::other)?;
+    Ok((export.feeds.len(), entries))
+  }
+
+  fn store_json(&mut self, export: &JsonExport) -> Result<()> {
+    let transaction = self.conn.transaction()?;
+    {
+      let mut upsert_feed = transaction.prepare(
+        "INSERT INTO feeds (url, title, position, last_fetched) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (url) DO UPDATE SET
+           last_fetched = COALESCE(feeds.last_fetched, excluded.last_fetched)
+         RETURNING id",
+      )?;
+      // Imported entries weren't seen in a fetch yet, like the ones from before seen_at
+      let mut upsert_entry = transaction.prepare(
+        "INSERT INTO entries (feed_id, title, published, plain_text, links, media, author, read, starred)
+         VALUES (?1, ?2, ?3, COALESCE(?4, ''), ?5, ?6, ?7, ?8, ?9)
+         ON CONFLICT (feed_id, title, published) DO UPDATE SET
+           plain_text = COALESCE(?4, plain_text),
+           links = excluded.links,
+           media = excluded.media,
+           author = COALESCE(excluded.author, author),
+           read = excluded.read,
+           starred = excluded.starred",
+      )?;
+      for (position, feed) in export.feeds.iter().enumerate() {
+        let last_fetched = feed
+          .last_fetched
+          .as_deref()
+          .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+          .map(|date| date.timestamp());
+        let feed_id: i64 = upsert_feed.query_row(
+          params![feed.url, feed.title, position, last_fetched],
+          |row| row.get(0),
+        )?;
+        for entry in &feed.entries {
+          let enclosures: Vec<Enclosure> = entry
+            .media
+            .iter()
+            .map(|m| Enclosure {
+              url: m.url.clone(),
+              mime_type: m.mime_type.clone(),
+            })
+            .collect();
+          upsert_entry.execute(params![
+            feed_id,
+            entry.title,
+            entry.published.as_deref().unwrap_or_default(),
+            entry.text,
+            entry.links.join("\n"),
+            encode_enclosures(&enclosures),
+            entry.author,
+            entry.read,
+            entry.starred,
+          ])?;
+        }
+      }
+    }
+    transaction.commit()
+  }
+
+  fn json_export(&self) -> Result<JsonExport> {
+    let mut feeds_stmt = self
+      .conn
+      .prepare("SELECT id, url, title, last_fetched FROM feeds ORDER BY position, id")?;
+    let mut entries_stmt = self.conn.prepare(
+      "SELECT title, published, author, links, media, read, starred, plain_text
+       FROM entries WHERE feed_id = ?1 ORDER BY id",
+    )?;
+    let feeds = feeds_stmt
+      .query_map([], |row| {
+        Ok((
+          row.get::<_, i64>(0)?,
+          row.get::<_, String>(1)?,
+          row.get::<_, String>(2)?,
+          row.get::<_, Option<i64>>(3)?,
+        ))
+      })?
+      .collect::<Result<Vec<_>>>()?;
+    let mut export = JsonExport {
+      version: 1,
+      feeds: Vec::with_capacity(feeds.len()),
+    };
+    for (feed_id, url, title, last_fetched) in feeds {
+      let entries = entries_stmt
+        .query_map(params![feed_id], |row| {
+          let published: String = row.get(1)?;
+          let links: String = row.get(3)?;
+          let text: String = row.get(7)?;
+          Ok(JsonEntry {
+            title: row.get(0)?,
+            published: (!published.is_empty()).then_some(published),
+            author: row.get(2)?,
+            links: links.lines().map(str::to_string).collect(),
+            media: decode_enclosures(&row.get::<_, String>(4)?)
+              .into_iter()
+              .map(|e| JsonMedia {
+                url: e.url,
+                mime_type: e.mime_type,
+              })
+              .collect(),
+            read: row.get(5)?,
+            starred: row.get(6)?,
+            text: (!text.is_empty()).then_some(text),
+          })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+      export.feeds.push(JsonFeed {
+        url,
+        title,
+        last_fetched: last_fetched
+          .and_then(|secs| DateTime::from_timestamp(secs, 0))
+          .map(|date| date.to_rfc3339()),
+        entries,
+      });
+    }
+    Ok(export)
+  }
+
+  /// Unread and total entries per feed URL, counted without loading any entry
+  pub fn unread_counts(&self) -> Result<HashMap<String, (usize, usize)>> {
+    let mut stmt = self.conn.prepare(
+      "SELECT f.url, COUNT(e.id) FILTER (WHERE e.read = 0), COUNT(e.id)
+       FROM feeds f LEFT JOIN entries e ON e.feed_id = f.id GROUP BY f.id",
+    )?;
+    let counts = stmt
+      .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+      .collect();
+    counts
+  }
+
+  /// ETag and Last-Modified values of every stored feed, keyed by feed URL
+  pub fn load_validators(&self) -> Result<HashMap<String, Validators>> {
+    let mut stmt = self
+      .conn
+      .prepare("SELECT url, etag, last_modified FROM feeds")?;
+    let rows = stmt.query_map([], |row| {
+      Ok((
+        row.get::<_, String>(0)?,
+        Validators {
+          etag: row.get(1)?,
+          last_modified: row.get(2)?,
+        },
+      ))
+    })?;
+    rows.collect()
+  }
+
+  /// Copies the stored read and starred state onto the entries of an in-memory feed
+  pub fn load_read_state(&self, feed: &mut Feed) -> Result<()> {
+    let mut stmt = self.conn.prepare(
+      "SELECT e.read, e.starred FROM entries e JOIN feeds f ON e.feed_id = f.id
+       WHERE f.url = ?1 AND e.title = ?2 AND e.published = ?3",
+    )?;
+    for entry in &mut feed.entries {
+      let published = entry.published.as_deref().unwrap_or_default();
+      // Entries that aren't stored yet are new
+      let (read, starred) = stmt
+        .query_row(params![feed.url, entry.title, published], |row| {
+          Ok((row.get(0)?, row.get(1)?))
+        })
+        .optional()?
+        .unwrap_or((false, false));
+      entry.read = read;
+      entry.starred = starred;
+    }
+    Ok(())
+  }
+
+  pub fn mark_entry_read(
+    &self,
+    feed_url: &str,
+    title: &str,
+    published: Option<&str>,
+  ) -> Result<()> {
+    self.conn.execute(
+      "UPDATE entries SET read = 1
+       WHERE feed_id = (SELECT id FROM feeds WHERE url = ?1) AND title = ?2 AND published = ?3",
+      params![feed_url, title, published.unwrap_or_default()],
+    )?;
+    Ok(())
+  }
+
+  /// Marks entries unread again in one transaction, as (feed URL, title, published)
+  pub fn mark_entries_unread(
+    &mut self,
+    entries: &[(String, String, Option<String>)],
+  ) -> Result<()> {
+    let transaction = self.conn.transaction()?;
+    {
+      let mut update = transaction.prepare(
+        "UPDATE entries SET read = 0
+         WHERE feed_id = (SELECT id FROM feeds WHERE url = ?1) AND title = ?2 AND published = ?3",
+      )?;
+      for (feed_url, title, published) in entries {
+        update.execute(params![
+          feed_url,
+          title,
+          published.as_deref().unwrap_or_default()
+        ])?;
+      }
+    }
+    transaction.commit()
+  }
+
+  pub fn mark_entry_starred(
+    &self,
+    feed_url: &str,
+    title: &str,
+    published: Option<&str>,
+  ) -> Result<()> {
+    self.set_entry_starred(feed_url, title, published, true)
+  }
+
+  pub fn mark_entry_unstarred(
+    &self,
+    feed_url: &str,
+    title: &str,
+    published: Option<&str>,
+  ) -> Result<()> {
+    self.set_entry_starred(feed_url, title, published, false)
+  }
+
+  fn set_entry_starred(
+    &self,
+    feed_url: &str,
+    title: &str,
+    published: Option<&str>,
+    starred: bool,
+  ) -> Result<()> {
+    self.conn.execute(
+      "UPDATE entries SET starred = ?4
+       WHERE feed_id = (SELECT id FROM feeds WHERE url = ?1) AND title = ?2 AND published = ?3",
+      params![feed_url, title, published.unwrap_or_default(), starred],
+    )?;
+    Ok(())
+  }
+
+  /// Marks every entry of one feed as read, returning how many entries changed
+  pub fn mark_feed_read(&self, feed_url: &str) -> Result<usize> {
+    self.conn.execute(
+      "UPDATE entries SET read = 1
+       WHERE read = 0 AND feed_id = (SELECT id FROM feeds WHERE url = ?1)",
+      params![feed_url],
+    )
+  }
+
+  /// Marks every stored entry as read, returning how many entries changed
+  pub fn mark_all_read(&self) -> Result<usize> {
+    self
+      .conn
+      .execute("UPDATE entries SET read = 1 WHERE read = 0", [])
+  }
+
+  /// Deletes read, unstarred entries published more than `max_age_days` ago and those
+  /// beyond the newest `max_per_feed` of their feed. Entries still in the latest fetch of
+  /// their feed are kept, they would come back as unread otherwise. Returns how many
+  /// entries were deleted.
+  pub fn prune_entries(
+    &self,
+    max_age_days: Option<u64>,
+    max_per_feed: Option<usize>,
+  ) -> Result<usize> {
+    let prunable = "read = 1 AND starred = 0 AND seen_at IS NOT NULL
+      AND seen_at < (SELECT last_fetched FROM feeds WHERE feeds.id = entries.feed_id)";
+    let mut deleted = 0;
+    if let Some(days) = max_age_days {
+      // Dates are stored as "YYYY-MM-DD HH:MM:SS UTC", which sorts chronologically
+      let cutoff = (Utc::now() - chrono::Duration::days(days as i64)).to_string();
+      deleted += self.conn.execute(
+        &format!(
+          "DELETE FROM entries WHERE {} AND published != '' AND published < ?1",
+          prunable
+        ),
+        params![cutoff],
+      )?;
+    }
+    if let Some(max_per_feed) = max_per_feed {
+      deleted += self.conn.execute(
+        &format!(
+          "DELETE FROM entries WHERE {} AND id IN (
+             SELECT id FROM (
+               SELECT id, ROW_NUMBER() OVER (
+                 PARTITION BY feed_id ORDER BY published DESC, id DESC
+               ) AS rank FROM entries
+             ) WHERE rank > ?1
+           )",
+          prunable
+        ),
+        params![max_per_feed],
+      )?;
+    }
+    Ok(deleted)
+  }
+
+  /// Rebuilds the database file so space freed by deleted entries is returned
+  pub fn compact(&self) -> Result<()> {
+    // The rebuilt pages go through the WAL, the checkpoint writes them back and empties it
+    self
+      .conn
+      .execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")
+  }
+
+  /// When the most recently fetched feed was fetched, `None` before the first refresh
+  pub fn last_refresh_time(&self) -> Result<Option<DateTime<Utc>>> {
+    let last_fetched: Option<i64> =
+      self
+        .conn
+        .query_row("SELECT MAX(last_fetched) FROM feeds", [], |row| row.get(0))?;
+    Ok(last_fetched.and_then(|secs| DateTime::from_timestamp(secs, 0)))
+  }
+
+  /// When each stored feed was last fetched with changes, by URL
+  pub fn fetch_times(&self) -> Result<HashMap<String, DateTime<Utc>>> {
+    let mut stmt = self
+      .conn
+      .prepare("SELECT url, last_fetched FROM feeds WHERE last_fetched IS NOT NULL")?;
+    let rows = stmt.query_map([], |row| {
+      Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    let mut times = HashMap::new();
+    for row in rows {
+      let (url, secs) = row?;
+      if let Some(date) = DateTime::from_timestamp(secs, 0) {
+        times.insert(url, date);
+      }
+    }
+    Ok(times)
+  }
+
+  pub fn stats(&self) -> Result<CacheStats> {
+    self.conn.query_row(
+      "SELECT (SELECT COUNT(*) FROM feeds),
+              (SELECT COUNT(*) FROM entries),
+              (SELECT COUNT(*) FROM entries WHERE read = 0),
+              (SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size())",
+      [],
+      |row| {
+        Ok(CacheStats {
+          feeds: row.get(0)?,
+          entries: row.get(1)?,
+          unread: row.get(2)?,
+          size: row.get(3)?,
+        })
+      },
+    )
+  }
+
+  /// Value stored under `key` by `save_state`
+  pub fn load_state(&self, key: &str) -> Result<Option<String>> {
+    self
+      .conn
+      .query_row(
+        "SELECT value FROM app_state WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+      )
+      .optional()
+  }
+
+  /// Stores state that outlives a session under `key`, `None` removes it
+  pub fn save_state(&self, key: &str, value: Option<&str>) -> Result<()> {
+    match value {
+      Some(value) => self.conn.execute(
+        "INSERT INTO app_state (key, value) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+      )?,
+      None => self
+        .conn
+        .execute("DELETE FROM app_state WHERE key = ?1", params![key])?,
+    };
+    Ok(())
+  }
+
+  /// Deletes all feeds and entries, returning the number of (entries, feeds) removed
+  pub fn clear_all(&self) -> Result<(usize, usize)> {
+    let entries = self.conn.execute("DELETE FROM entries", [])?;
+    let feeds = self.conn.execute("DELETE FROM feeds", [])?;
+    Ok((entries, feeds))
+  }
+
+  /// Deletes a feed with all its entries, starred ones included
+  pub fn delete_feed(&self, url: &str) -> Result<()> {
+    self
+      .conn
+      .execute("DELETE FROM feeds WHERE url = ?1", params![url])?;
+    Ok(())
+  }
+
+  /// Stores a feed that moved under its new URL with its entries and read state.
+  /// When `new_url` is already stored the old feed is deleted instead.
+  pub fn move_feed(&self, url: &str, new_url: &str) -> Result<()> {
+    let taken: bool = self.conn.query_row(
+      "SELECT COUNT(*) > 0 FROM feeds WHERE url = ?1",
+      params![new_url],
+      |row| row.get(0),
+    )?;
+    if taken {
+      return self.delete_feed(url);
+    }
+    self.conn.execute(
+      "UPDATE feeds SET url = ?2 WHERE url = ?1",
+      params![url, new_url],
+    )?;
+    Ok(())
+  }
+
+  /// Marks entries read that Newsboat's cache.db has as read, matching on the
+  /// entry link or on title and publication date. Returns how many entries changed.
+  pub fn import_newsboat_read_state(&self, newsboat_db: &Path) -> Result<usize> {
+    let newsboat = Connection::open_with_flags(newsboat_db, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    // Older Newsboat versions lack some columns, so check before querying
+    let columns = newsboat
+      .prepare("SELECT name FROM pragma_table_info('rss_item')")?
+      .query_map([], |row| row.get::<_, String>(0))?
+      .collect::<Result<Vec<_>>>()?;
+    for column in ["feedurl", "url", "title", "pubDate", "unread"] {
+      if !columns.iter().any(|c| c == column) {
+        return Err(rusqlite::Error::InvalidColumnName(format!(
+          "rss_item.{}",
+          column
+        )));
+      }
+    }
+
+    let mut read_items = newsboat.prepare(
+      "SELECT feedurl, COALESCE(url, ''), COALESCE(title, ''), COALESCE(pubDate, 0)
+       FROM rss_item WHERE unread = 0",
+    )?;
+    let mut mark_read = self.conn.prepare(
+      "UPDATE entries SET read = 1
+       WHERE read = 0
+         AND feed_id = (SELECT id FROM feeds WHERE url = ?1)
+         AND ((?2 != '' AND instr(char(10) || links || char(10), char(10) || ?2 || char(10)) > 0)
+           OR (title = ?3 AND published = ?4))",
+    )?;
+    let mut matched = 0;
+    let rows = read_items.query_map([], |row| {
+      Ok((
+        row.get::<_, String>(0)?,
+        row.get::<_, String>(1)?,
+        row.get::<_, String>(2)?,
+        row.get::<_, i64>(3)?,
+      ))
+    })?;
+    for row in rows {
+      let (feed_url, url, title, pub_date) = row?;
+      // Dates are stored in the same format `parse_feed` produces
+      let published = DateTime::from_timestamp(pub_date, 0)
+        .map(|d| d.to_string())
+        .unwrap_or_default();
+      matched += mark_read.execute(params![feed_url, url, title, published])?;
+    }
+    Ok(matched)
+  }
+}
+
+/// Upserts a feed and its entries with statements cached on the connection, keeping
+/// the read state of entries already stored. Returns the entries inserted into a feed
+/// that was stored before.
+fn upsert_feed<'a>(
+  conn: &Connection,
+  feed: &'a Feed,
+  position: usize,
+) -> Result<Vec<&'a FeedEntry>> {
+  let known = conn
+    .prepare_cached("SELECT 1 FROM feeds WHERE url = ?1")?
+    .exists(params![feed.url])?;
+  let feed_id: i64 = conn
+    .prepare_cached(
+      "INSERT INTO feeds (url, title, position, last_fetched, etag, last_modified)
+       VALUES (?1, ?2, ?3, strftime('%s', 'now'), ?4, ?5)
+       ON CONFLICT (url) DO UPDATE SET
+         title = excluded.title,
+         position = excluded.position,
+         last_fetched = excluded.last_fetched,
+         etag = excluded.etag,
+         last_modified = excluded.last_modified
+       RETURNING id",
+    )?
+    .query_row(
+      params![
+        feed.url,
+        feed.title,
+        position,
+        feed.validators.etag,
+        feed.validators.last_modified,
+      ],
+      |row| row.get(0),
+    )?;
+  let mut insert_entry = conn.prepare_cached(
+    "INSERT INTO entries (feed_id, title, published, plain_text, links, media, author, seen_at)
+     VALUES (?1, ?2, ?3, COALESCE(?4, ''), ?5, ?6, ?7, strftime('%s', 'now'))
+     ON CONFLICT (feed_id, title, published) DO UPDATE SET
+       plain_text = COALESCE(?4, plain_text),
+       author = excluded.author,
+       links = excluded.links,
+       media = excluded.media,
+       seen_at = excluded.seen_at",
+  )?;
+  // The upsert can't tell an insert from an update
+  let mut entry_exists = conn
+    .prepare_cached("SELECT 1 FROM entries WHERE feed_id = ?1 AND title = ?2 AND published = ?3")?;
+  let mut inserted = Vec::new();
+  for entry in &feed.entries {
+    let published = entry.published.as_deref().unwrap_or_default();
+    if known && !entry_exists.exists(params![feed_id, entry.title, published])? {
+      inserted.push(entry);
+    }
+    insert_entry.execute(params![
+      feed_id,
+      entry.title,
+      entry.published.as_deref().unwrap_or_default(),
+      entry.plain_text,
+      entry.links.join("\n"),
+      encode_enclosures(&entry.enclosures),
+      entry.author,
+    ])?;
+  }
+  Ok(inserted)
+}
+
+/// Resets the failures of a feed after a fetch that worked, adding the number of
+/// `entries` it returned to the average unless it was unchanged
+fn record_success(conn: &Connection, url: &str, entries: Option<usize>) -> Result<()> {
+  conn
+    .prepare_cached(
+      "UPDATE feeds SET
+         consecutive_failures = 0,
+         last_success = strftime('%s', 'now'),
+         fetch_count = fetch_count + (?2 IS NOT NULL),
+         fetched_entries = fetched_entries + COALESCE(?2, 0)
+       WHERE url = ?1",
+    )?
+    .execute(params![url, entries])?;
+  Ok(())
+}
+
+/// Stores enclosures in the `media` column, one "url<TAB>type" per line
+fn encode_enclosures(enclosures: &[Enclosure]) -> String {
+  enclosures
+    .iter()
+    .map(|e| match &e.mime_type {
+      Some(mime_type) => format!("{}\t{}", e.url, mime_type),
+      None => e.url.clone(),
+    })
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// Reverses `encode_enclosures`, older caches hold a single URL without a type
+fn decode_enclosures(media: &str) -> Vec<Enclosure> {
+  media
+    .lines()
+    .filter(|line| !line.is_empty())
+    .map(|line| match line.split_once('\t') {
+      Some((url, mime_type)) => Enclosure {
+        url: url.to_string(),
+        mime_type: Some(mime_type.to_string()),
+      },
+      None => Enclosure {
+        url: line.to_string(),
+        mime_type: None,
+      },
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn cache() -> FeedCache {
+    FeedCache::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+  }
+
+  fn entry(title: &str, published: &str) -> FeedEntry {
+    FeedEntry {
+      title: title.to_string(),
+      published: Some(published.to_string()),
+      author: None,
+      published_at: published.parse().ok(),
+      plain_text: Some(format!("Text of {}", title)),
+      links: Vec::new(),
+      enclosures: Vec::new(),
+      read: false,
+      starred: false,
+      feed_url: "http://example.com/feed.xml".to_string(),
+    }
+  }
+
+  fn feed(entries: Vec<FeedEntry>) -> Feed {
+    Feed {
+      url: "http://example.com/feed.xml".to_string(),
+      title: "Example".to_string(),
+      entries,
+      tags: None,
+      muted: false,
+      position: 0,
+      validators: Validators::default(),
+      display_max_age_days: None,
+      kind: FeedKind::Subscription,
+    }
+  }
+
+  fn titles(cache: &FeedCache) -> Vec<String> {
+    let mut stmt = cache
+      .conn
+      .prepare("SELECT title FROM entries ORDER BY title")
+      .unwrap();
+    let titles = stmt.query_map([], |row| row.get(0)).unwrap();
+    titles.collect::<Result<_>>().unwrap()
+  }
+
+  fn mark_read(cache: &FeedCache, entry: &FeedEntry) {
+    let url = &entry.feed_url;
+    cache
+      .mark_entry_read(url, &entry.title, entry.published.as_deref())
+      .unwrap();
+  }
+
+  /// Makes the stored entries look like they came from an earlier fetch than the next
+  fn age_entries(cache: &FeedCache) {
+    cache
+      .conn
+      .execute("UPDATE entries SET seen_at = seen_at - 3600", [])
+      .unwrap();
+  }
+
+  /// 500 entries with long bodies
+  fn long_feed() -> Feed {
+    feed(
+      (0..500)
+        .map(|i| FeedEntry {
+          plain_text: Some("Lorem ipsum dolor sit amet. ".repeat(200)),
+          ..entry(&format!("Entry {}", i), "2024-01-01 00:00:00 UTC")
+        })
+        .collect(),
+    )
+  }
+
+  #[test]
+  fn saves_and_loads_a_long_feed_without_its_bodies() {
+    let cache = cache();
+    let feed = long_feed();
+    cache.save_feed(&feed, 0).unwrap();
+    mark_read(&cache, &feed.entries[42]);
+    // Saving the same entries again keeps their read state and inserts none
+    assert!(cache.save_feed(&feed, 0).unwrap().is_empty());
+
+    let mut loaded = cache.load_feed(&feed.url).unwrap().unwrap();
+    assert_eq!(loaded.entries.len(), 500);
+    assert!(loaded.entries.iter().all(|e| e.plain_text.is_none()));
+    assert!(loaded.entries[42].read);
+    assert_eq!(loaded.entries.iter().filter(|e| e.read).count(), 1);
+
+    cache.load_texts(&mut loaded).unwrap();
+    assert!(loaded
+      .entries
+      .iter()
+      .all(|e| e.plain_text == feed.entries[0].plain_text));
+  }
+
+  /// Run with `cargo test --release -- --ignored`, the bounds only catch a large slowdown
+  #[test]
+  #[ignore]
+  fn saving_and_loading_a_long_feed_is_fast() {
+    use std::time::{Duration, Instant};
+    let cache = cache();
+    let feed = long_feed();
+    let started = Instant::now();
+    cache.save_feed(&feed, 0).unwrap();
+    let saved_in = started.elapsed();
+    let started = Instant::now();
+    cache.load_feed(&feed.url).unwrap().unwrap();
+    let loaded_in = started.elapsed();
+    assert!(saved_in < Duration::from_secs(2), "Saved in {:?}", saved_in);
+    assert!(
+      loaded_in < Duration::from_millis(500),
+      "Loaded in {:?}",
+      loaded_in
+    );
+  }
+
+  #[test]
+  fn prunes_old_read_entries_only() {
+    let cache = cache();
+    let recent = (Utc::now() - chrono::Duration::days(1)).to_string();
+    let entries = vec![
+      entry("Old read", "2000-01-01 00:00:00 UTC"),
+      entry("Old unread", "2000-01-02 00:00:00 UTC"),
+      entry("Old starred", "2000-01-03 00:00:00 UTC"),
+      entry("Old but still listed", "2000-01-04 00:00:00 UTC"),
+      entry("Recent read", &recent),
+    ];
+    cache.save_feed(&feed(entries.clone()), 0).unwrap();
+    for entry in &entries {
+      if entry.title != "Old unread" {
+        mark_read(&cache, entry);
+      }
+    }
+    let starred = &entries[2];
+    cache
+      .mark_entry_starred(
+        &starred.feed_url,
+        &starred.title,
+        starred.published.as_deref(),
+      )
+      .unwrap();
+    age_entries(&cache);
+    cache.save_feed(&feed(vec![entries[3].clone()]), 0).unwrap();
+
+    assert_eq!(cache.prune_entries(Some(30), None).unwrap(), 1);
+    assert_eq!(
+      titles(&cache),
+      [
+        "Old but still listed",
+        "Old starred",
+        "Old unread",
+        "Recent read"
+      ]
+    );
+  }
+
+  #[test]
+  fn caps_read_entries_per_feed() {
+    let cache = cache();
+    let entries: Vec<FeedEntry> = (0..5)
+      .map(|day| {
+        entry(
+          &format!("Day {}", day),
+          &format!("2024-01-0{} 00:00:00 UTC", day + 1),
+        )
+      })
+      .collect();
+    cache.save_feed(&feed(entries.clone()), 0).unwrap();
+    // The oldest stays unread
+    for entry in &entries[1..] {
+      mark_read(&cache, entry);
+    }
+    age_entries(&cache);
+    cache.save_feed(&feed(Vec::new()), 0).unwrap();
+
+    assert_eq!(cache.prune_entries(None, Some(2)).unwrap(), 2);
+    assert_eq!(titles(&cache), ["Day 0", "Day 3", "Day 4"]);
+  }
+
+  #[test]
+  fn pruning_without_limits_keeps_everything() {
+    let cache = cache();
+    let entries = vec![entry("Old read", "2000-01-01 00:00:00 UTC")];
+    cache.save_feed(&feed(entries.clone()), 0).unwrap();
+    mark_read(&cache, &entries[0]);
+    age_entries(&cache);
+    cache.save_feed(&feed(Vec::new()), 0).unwrap();
+    assert_eq!(cache.prune_entries(None, None).unwrap(), 0);
+    assert_eq!(titles(&cache), ["Old read"]);
+  }
+
+  #[test]
+  fn deleting_a_feed_deletes_its_entries_and_scroll_positions() {
+    let cache = cache();
+    let foreign_keys: bool = cache
+      .conn
+      .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+      .unwrap();
+    assert!(foreign_keys);
+    let deleted = feed(vec![
+      entry("Scrolled", "2024-01-01 00:00:00 UTC"),
+      entry("Unread", "2024-01-02 00:00:00 UTC"),
+    ]);
+    let other_url = "http://example.org/feed.xml";
+    let kept = Feed {
+      url: other_url.to_string(),
+      entries: vec![FeedEntry {
+        feed_url: other_url.to_string(),
+        ..entry("Kept", "2024-01-01 00:00:00 UTC")
+      }],
+      ..feed(Vec::new())
+    };
+    cache.save_feed(&deleted, 0).unwrap();
+    cache.save_feed(&kept, 1).unwrap();
+    let scrolled = |url| {
+      cache
+        .load_scroll_position(url, "Scrolled", Some("2024-01-01 00:00:00 UTC"))
+        .unwrap()
+    };
+    cache
+      .save_scroll_position(
+        &deleted.url,
+        "Scrolled",
+        Some("2024-01-01 00:00:00 UTC"),
+        12,
+      )
+      .unwrap();
+    assert_eq!(scrolled(&deleted.url), 12);
+
+    cache.delete_feed(&deleted.url).unwrap();
+    assert_eq!(titles(&cache), ["Kept"]);
+    assert_eq!(scrolled(&deleted.url), 0);
+    // Saving the feed again starts it over instead of finding its old rows
+    cache.save_feed(&deleted, 0).unwrap();
+    assert_eq!(scrolled(&deleted.url), 0);
+    let orphans: usize = cache
+      .conn
+      .query_row(
+        "SELECT COUNT(*) FROM entries WHERE feed_id NOT IN (SELECT id FROM feeds)",
+        [],
+        |row| row.get(0),
+      )
+      .unwrap();
+    assert_eq!(orphans, 0);
+  }
+
+  /// A cache as left by the first `migrations`, with a read entry
+  fn legacy_cache(migrations: usize) -> FeedCache {
+    let conn = Connection::open_in_memory().unwrap();
+    conn
+      .execute_batch(&MIGRATIONS[..migrations].join("\n"))
+      .unwrap();
+    conn
+      .execute_batch(
+        "INSERT INTO feeds (id, url, title, position) VALUES
+           (1, 'http://example.com/feed.xml', 'Example', 3);
+         INSERT INTO entries (feed_id, title, published, plain_text, links, media, read)
+           VALUES (1, 'Old', '2024-01-01 00:00:00 UTC', 'Text of Old', 'http://example.com/1', '', 1);",
+      )
+      .unwrap();
+    conn
+      .pragma_update(None, "user_version", migrations)
+      .unwrap();
+    FeedCache { conn }
+  }
+
+  fn user_version(cache: &FeedCache) -> usize {
+    cache
+      .conn
+      .pragma_query_value(None, "user_version", |row| row.get(0))
+      .unwrap()
+  }
+
+  /// Checks the entry of `legacy_cache` survived and the latest columns are usable
+  fn assert_upgraded(cache: &FeedCache) {
+    assert_eq!(user_version(cache), MIGRATIONS.len());
+    let feed = cache
+      .load_feed("http://example.com/feed.xml")
+      .unwrap()
+      .unwrap();
+    assert_eq!((feed.title.as_str(), feed.position), ("Example", 3));
+    let [old] = feed.entries.as_slice() else {
+      panic!("expected one entry, got {}", feed.entries.len());
+    };
+    assert_eq!(old.title, "Old");
+    assert_eq!(old.links, ["http://example.com/1"]);
+    assert!(old.read && !old.starred);
+    cache
+      .save_scroll_position(&feed.url, "Old", old.published.as_deref(), 7)
+      .unwrap();
+    cache.record_failure(&feed.url).unwrap();
+    assert_eq!(
+      cache.feed_health().unwrap()[&feed.url].consecutive_failures,
+      1
+    );
+  }
+
+  #[test]
+  fn upgrades_a_cache_of_an_earlier_version() {
+    let cache = legacy_cache(3);
+    cache.init_schema().unwrap();
+    assert_upgraded(&cache);
+  }
+
+  #[test]
+  fn upgrading_drops_the_entries_of_deleted_feeds() {
+    let cache = legacy_cache(7);
+    cache
+      .conn
+      .execute_batch(
+        "INSERT INTO entries (feed_id, title, plain_text, links, media)
+           VALUES (2, 'Orphan', '', '', '');",
+      )
+      .unwrap();
+    cache.init_schema().unwrap();
+    assert_eq!(titles(&cache), ["Old"]);
+    assert_upgraded(&cache);
+  }
+
+  #[test]
+  fn new_caches_get_every_migration_once() {
+    let cache = cache();
+    assert_eq!(user_version(&cache), MIGRATIONS.len());
+    cache.init_schema().unwrap();
+    assert_eq!(user_version(&cache), MIGRATIONS.len());
+  }
+}
@@ -1,55 +1,903 @@
+use crate::theme::ThemeConfig;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use dirs::config_dir;
 use serde::Deserialize;
-use std::{fs, process::exit};
+use std::{
+  collections::HashMap,
+  fmt, fs,
+  io::Write,
+  path::{Path, PathBuf},
+  sync::OnceLock,
+};
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize)]
 pub struct Feeds {
   pub link: String,
   pub name: Option<String>,
   pub tags: Option<Vec<String>>,
+  #[serde(default)]
+  pub muted: bool,
+  /// Only show entries published within this many days, older ones stay cached
+  pub display_max_age_days: Option<u64>,
+  /// HTTP Basic Auth for private feeds
+  pub username: Option<String>,
+  pub password: Option<Secret>,
+  /// Extra request headers, e.g. a token the feed wants
+  #[serde(default)]
+  pub headers: HashMap<String, Secret>,
+}
+
+/// A credential from urls.toml, left out of debug output so it can't end up in logs
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl fmt::Debug for Secret {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("\"***\"")
+  }
+}
+
+/// A virtual feed collecting the entries of every feed matching `query`
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryFeed {
+  pub name: String,
+  pub query: String,
+  /// List an entry that several matching feeds syndicate only once
+  #[serde(default)]
+  pub dedupe: bool,
+  /// Leave out entries once they're read
+  #[serde(default)]
+  pub unread_only: bool,
 }
 
+/// Contents of urls.toml
 #[derive(Debug, Deserialize)]
-struct Config {
+struct UrlsFile {
   feeds: Vec<Feeds>,
+  #[serde(default)]
+  queries: Vec<QueryFeed>,
+}
+
+/// Everything read from the config directory at startup
+#[derive(Debug)]
+pub struct Config {
+  /// Options of config.toml, the defaults when it doesn't exist
+  pub settings: UserConfig,
+  pub feeds: Vec<Feeds>,
+  pub queries: Vec<QueryFeed>,
+}
+
+impl Config {
+  /// Reads config.toml and urls.toml, failing on a missing urls.toml or on either
+  /// file not parsing
+  pub fn load() -> Result<Config, String> {
+    let settings = parse_config()?;
+    let urls = parse_urls_file()?;
+    Ok(Config {
+      settings,
+      feeds: urls.feeds,
+      queries: urls.queries,
+    })
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct UserConfig {
+  pub read_count: ReadCount,
+  pub confirm_destructive: bool,
+  /// Maximum number of feeds fetched at the same time
+  pub fetch_concurrency: usize,
+  /// Seconds to wait for a single feed before giving up on it
+  pub fetch_timeout_secs: u64,
+  /// User-Agent header sent with feed requests, defaults to "shinbun/<version>"
+  pub user_agent: Option<String>,
+  /// Proxy for every feed request instead of the one in HTTP_PROXY or HTTPS_PROXY,
+  /// hosts in NO_PROXY are still fetched directly
+  pub proxy: Option<String>,
+  /// Fetch every feed on launch, the cached feeds are shown in the meantime
+  pub refresh_on_launch: bool,
+  /// Minutes between automatic refreshes, 0 disables them
+  pub refresh_interval_minutes: u64,
+  /// Open the first unread entry straight away when entering a feed
+  pub auto_open_first_unread: bool,
+  /// Mark entries read once they're scrolled to the bottom instead of when they're opened
+  pub mark_read_on_scroll_end: bool,
+  /// Show the feeds and entries side by side instead of one at a time
+  pub split_view: bool,
+  /// Draw borders around the tags, feeds and entries lists
+  pub show_borders: bool,
+  /// Moving past the last feed or entry selects the first one and the other way around,
+  /// also for jumping to the next unread entry
+  pub wrap_navigation: bool,
+  /// List feeds under a header for their first tag, after the query feeds
+  pub group_by_tag: bool,
+  /// Select with clicks and scroll with the wheel, text is then selected with Shift held
+  pub mouse: bool,
+  /// Show the number of unread entries in the terminal title
+  pub terminal_title: bool,
+  /// Per terminal size overrides of `split_view`
+  pub layouts: LayoutConfig,
+  /// How links are shown in the entry view, toggled at runtime with `L`
+  pub link_style: LinkStyle,
+  /// Directory saved entries are written to, defaults to the documents directory
+  pub save_dir: Option<PathBuf>,
+  pub save_format: SaveFormat,
+  /// Days to keep read entries in the cache, unset keeps them forever
+  pub retention_days: Option<u64>,
+  /// Read entries kept in the cache per feed, unset keeps all of them
+  pub max_entries_per_feed: Option<usize>,
+  /// Newest entries taken from a fetched feed, unset or 0 takes all of them
+  pub max_parsed_entries: Option<usize>,
+  /// How entry dates are shown in the entries list
+  pub date_format: DateFormat,
+  /// What the rows of the entries list show, in this order
+  pub entry_columns: Vec<EntryColumn>,
+  /// Order of the entries list, changed at runtime with `o`
+  pub entry_sort: EntrySort,
+  /// Symbols marking the state of each row in the entries list
+  pub glyphs: Glyphs,
+  /// Keys per action name, replacing the default keys of that action
+  pub keybindings: HashMap<String, Keys>,
+  /// Color preset and overrides
+  pub theme: ThemeConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Keys {
+  One(String),
+  Many(Vec<String>),
+}
+
+/// Everything a key can be bound to outside of popups
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+  Quit,
+  Next,
+  Prev,
+  Enter,
+  Back,
+  MoveUp,
+  MoveDown,
+  PageDown,
+  PageUp,
+  Top,
+  Bottom,
+  NextUnread,
+  PrevUnread,
+  Search,
+  Tags,
+  Refresh,
+  RefreshFeed,
+  Subscribe,
+  AddFeed,
+  DeleteFeed,
+  Mute,
+  MarkFeedRead,
+  UnreadOnly,
+  SortEntries,
+  LinkStyle,
+  Wrap,
+  SplitView,
+  Borders,
+  Save,
+  Star,
+  Play,
+  CopyLink,
+  NextLink,
+  PrevLink,
+  MarkAllRead,
+  UndoMarkRead,
+  ClearCache,
+  Diagnostics,
+  Help,
+}
+
+impl Action {
+  /// In the order the help popup lists them
+  pub const ALL: [Action; 39] = [
+    Action::Quit,
+    Action::Next,
+    Action::Prev,
+    Action::Enter,
+    Action::Back,
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::PageDown,
+    Action::PageUp,
+    Action::Top,
+    Action::Bottom,
+    Action::NextUnread,
+    Action::PrevUnread,
+    Action::Search,
+    Action::Tags,
+    Action::Refresh,
+    Action::RefreshFeed,
+    Action::Subscribe,
+    Action::AddFeed,
+    Action::DeleteFeed,
+    Action::Mute,
+    Action::MarkFeedRead,
+    Action::UnreadOnly,
+    Action::SortEntries,
+    Action::LinkStyle,
+    Action::Wrap,
+    Action::SplitView,
+    Action::Borders,
+    Action::Save,
+    Action::Star,
+    Action::Play,
+    Action::CopyLink,
+    Action::NextLink,
+    Action::PrevLink,
+    Action::MarkAllRead,
+    Action::UndoMarkRead,
+    Action::ClearCache,
+    Action::Diagnostics,
+    Action::Help,
+  ];
+
+  /// Name of the action in the `[keybindings]` table
+  pub fn name(&self) -> &'static str {
+    match self {
+      Action::Quit => "quit",
+      Action::Next => "next",
+      Action::Prev => "prev",
+      Action::Enter => "enter",
+      Action::Back => "back",
+      Action::MoveUp => "move_up",
+      Action::MoveDown => "move_down",
+      Action::PageDown => "page_down",
+      Action::PageUp => "page_up",
+      Action::Top => "top",
+      Action::Bottom => "bottom",
+      Action::NextUnread => "next_unread",
+      Action::PrevUnread => "prev_unread",
+      Action::Search => "search",
+      Action::Tags => "tags",
+      Action::Refresh => "refresh",
+      Action::RefreshFeed => "refresh_feed",
+      Action::Subscribe => "subscribe",
+      Action::AddFeed => "add_feed",
+      Action::DeleteFeed => "delete_feed",
+      Action::Mute => "mute",
+      Action::MarkFeedRead => "mark_feed_read",
+      Action::UnreadOnly => "unread_only",
+      Action::SortEntries => "sort_entries",
+      Action::LinkStyle => "link_style",
+      Action::Wrap => "wrap",
+      Action::SplitView => "split_view",
+      Action::Borders => "borders",
+      Action::Save => "save",
+      Action::Star => "star",
+      Action::Play => "play",
+      Action::CopyLink => "copy_link",
+      Action::NextLink => "next_link",
+      Action::PrevLink => "prev_link",
+      Action::MarkAllRead => "mark_all_read",
+      Action::UndoMarkRead => "undo_mark_read",
+      Action::ClearCache => "clear_cache",
+      Action::Diagnostics => "diagnostics",
+      Action::Help => "help",
+    }
+  }
+
+  pub fn description(&self) -> &'static str {
+    match self {
+      Action::Quit => "Quit",
+      Action::Next => "Next item, scroll down in an entry",
+      Action::Prev => "Previous item, scroll up in an entry",
+      Action::Enter => "Open feed or entry",
+      Action::Back => "Go back",
+      Action::MoveUp => "Move feed up",
+      Action::MoveDown => "Move feed down",
+      Action::PageDown => "Scroll down a page in an entry",
+      Action::PageUp => "Scroll up a page in an entry",
+      Action::Top => "First item (press letters twice), top of an entry",
+      Action::Bottom => "Last item, bottom of an entry",
+      Action::NextUnread => {
+        "Next unread entry, in the next feeds too, or the next match in the entry"
+      }
+      Action::PrevUnread => "Previous unread entry, in the feeds above too, or the previous match",
+      Action::Search => "Search entries, an empty search closes the results, or find in the entry",
+      Action::Tags => "Show or hide the tags to filter feeds by",
+      Action::Refresh => "Refresh all feeds",
+      Action::RefreshFeed => "Refresh selected feed",
+      Action::Subscribe => "Subscribe to pasted URLs",
+      Action::AddFeed => "Add a feed with a name and tags",
+      Action::DeleteFeed => "Unsubscribe from feed",
+      Action::Mute => "Mute or unmute feed",
+      Action::MarkFeedRead => "Mark all entries in feed as read",
+      Action::UnreadOnly => "Show only unread feeds and entries",
+      Action::SortEntries => "Change the order of entries",
+      Action::LinkStyle => "Show links inline or as footnotes",
+      Action::Wrap => "Wrap long lines of entry, or scroll them with Left and Right",
+      Action::SplitView => "Show feeds and entries side by side or one at a time",
+      Action::Borders => "Show or hide the borders of the lists",
+      Action::Save => "Save entry to disk",
+      Action::Star => "Star or unstar entry",
+      Action::Play => "Play audio or video of entry",
+      Action::CopyLink => "Copy link of entry",
+      Action::NextLink => "Focus next link in entry",
+      Action::PrevLink => "Focus previous link in entry",
+      Action::MarkAllRead => "Mark all entries as read",
+      Action::UndoMarkRead => "Mark the entries of the last feed or all read unread again",
+      Action::ClearCache => "Clear the cache",
+      Action::Diagnostics => "Show how fetching each feed has gone",
+      Action::Help => "Toggle this help",
+    }
+  }
+
+  fn default_keys(&self) -> &'static [&'static str] {
+    match self {
+      Action::Quit => &["q", "Q"],
+      Action::Next => &["j", "Down"],
+      Action::Prev => &["k", "Up"],
+      Action::Enter => &["l", "Right", "Enter"],
+      Action::Back => &["h", "Left", "Backspace"],
+      Action::MoveUp => &["K"],
+      Action::MoveDown => &["J"],
+      Action::PageDown => &["PageDown", "Space"],
+      Action::PageUp => &["PageUp"],
+      Action::Top => &["Home", "g"],
+      Action::Bottom => &["End", "G"],
+      Action::NextUnread => &["n"],
+      Action::PrevUnread => &["N"],
+      Action::Search => &["/"],
+      Action::Tags => &["t"],
+      Action::Refresh => &["r"],
+      Action::RefreshFeed => &["R"],
+      Action::Subscribe => &["b"],
+      Action::AddFeed => &["a"],
+      Action::DeleteFeed => &["d"],
+      Action::Mute => &["M"],
+      Action::MarkFeedRead => &["A"],
+      Action::UnreadOnly => &["u"],
+      Action::SortEntries => &["o"],
+      Action::LinkStyle => &["L"],
+      Action::Wrap => &["w"],
+      Action::SplitView => &["v"],
+      Action::Borders => &["B"],
+      Action::Save => &["s"],
+      Action::Star => &["f"],
+      Action::Play => &["p"],
+      Action::CopyLink => &["y"],
+      Action::NextLink => &["Tab"],
+      Action::PrevLink => &["BackTab"],
+      Action::MarkAllRead => &["C-a"],
+      Action::UndoMarkRead => &["U"],
+      Action::ClearCache => &["X"],
+      Action::Diagnostics => &["D"],
+      Action::Help => &["?"],
+    }
+  }
+}
+
+/// A key with the Control and Alt modifiers it needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+  code: KeyCode,
+  modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+  /// Parses names like "q", "Enter", "PageDown", "C-a" (Control) or "A-x" (Alt)
+  pub fn parse(name: &str) -> Result<Self, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    loop {
+      if let Some(key) = rest.strip_prefix("C-").filter(|k| !k.is_empty()) {
+        modifiers |= KeyModifiers::CONTROL;
+        rest = key;
+      } else if let Some(key) = rest.strip_prefix("A-").filter(|k| !k.is_empty()) {
+        modifiers |= KeyModifiers::ALT;
+        rest = key;
+      } else {
+        break;
+      }
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+      (Some(c), None) => KeyCode::Char(c),
+      _ => match rest.to_lowercase().as_str() {
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "backspace" => KeyCode::Backspace,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "space" => KeyCode::Char(' '),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        other => match other.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+          Some(n @ 1..=12) => KeyCode::F(n),
+          _ => return Err(format!("Unknown key \"{}\"", name)),
+        },
+      },
+    };
+    Ok(KeyBinding { code, modifiers })
+  }
+
+  fn matches(&self, event: &KeyEvent) -> bool {
+    // Shift is part of the character already, so only Control and Alt have to agree
+    let relevant = KeyModifiers::CONTROL | KeyModifiers::ALT;
+    self.code == event.code && self.modifiers == event.modifiers & relevant
+  }
+}
+
+impl fmt::Display for KeyBinding {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.modifiers.contains(KeyModifiers::CONTROL) {
+      write!(f, "C-")?;
+    }
+    if self.modifiers.contains(KeyModifiers::ALT) {
+      write!(f, "A-")?;
+    }
+    match self.code {
+      KeyCode::Char(' ') => write!(f, "Space"),
+      KeyCode::Char(c) => write!(f, "{}", c),
+      KeyCode::Up => write!(f, "↑"),
+      KeyCode::Down => write!(f, "↓"),
+      KeyCode::Left => write!(f, "←"),
+      KeyCode::Right => write!(f, "→"),
+      KeyCode::F(n) => write!(f, "F{}", n),
+      code => write!(f, "{:?}", code),
+    }
+  }
+}
+
+/// Resolves key presses to actions, built from the defaults and `[keybindings]`
+#[derive(Debug)]
+pub struct KeyMap {
+  bindings: Vec<(Action, Vec<KeyBinding>)>,
+}
+
+impl KeyMap {
+  pub fn action(&self, event: &KeyEvent) -> Option<Action> {
+    self
+      .bindings
+      .iter()
+      .find(|(_, keys)| keys.iter().any(|k| k.matches(event)))
+      .map(|(action, _)| *action)
+  }
+
+  /// Keys bound to `action`, for the help popup
+  pub fn keys(&self, action: Action) -> &[KeyBinding] {
+    self
+      .bindings
+      .iter()
+      .find(|(a, _)| *a == action)
+      .map_or(&[], |(_, keys)| keys.as_slice())
+  }
 }
 
-//#[derive(Debug, Deserialize)]
-//struct UserConfig {
-//  refresh_on_launch: bool,
-//}
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Glyphs {
+  pub unread: String,
+  pub read: String,
+  pub starred: String,
+}
+
+impl Default for Glyphs {
+  fn default() -> Self {
+    Glyphs {
+      unread: "●".to_string(),
+      read: "○".to_string(),
+      starred: "★".to_string(),
+    }
+  }
+}
+
+/// File format of entries saved with `s`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveFormat {
+  #[default]
+  Text,
+  Markdown,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateFormat {
+  /// Day and month, like "05 Mar"
+  #[default]
+  Absolute,
+  /// Time since publication, like "2h" or "3d"
+  Relative,
+}
+
+/// A column of the entries list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryColumn {
+  /// Unread, read or starred mark
+  Glyph,
+  Date,
+  /// Title of the feed the entry is from, for query feeds and search results
+  Source,
+  Author,
+  Title,
+}
+
+/// Undated entries always come last when sorting by date
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntrySort {
+  #[default]
+  DateDesc,
+  DateAsc,
+  /// Unread entries newest first, then read ones newest first
+  UnreadFirst,
+  Title,
+}
+
+impl EntrySort {
+  /// The order `o` switches to
+  pub fn next(self) -> Self {
+    match self {
+      EntrySort::DateDesc => EntrySort::DateAsc,
+      EntrySort::DateAsc => EntrySort::UnreadFirst,
+      EntrySort::UnreadFirst => EntrySort::Title,
+      EntrySort::Title => EntrySort::DateDesc,
+    }
+  }
 
-pub fn parse_feed_urls() -> Vec<Feeds> {
-  // Read the configuration file
-  let url_file = format!(
-    "{}/shinbun/urls.toml",
-    config_dir()
+  pub fn label(self) -> &'static str {
+    match self {
+      EntrySort::DateDesc => "newest first",
+      EntrySort::DateAsc => "oldest first",
+      EntrySort::UnreadFirst => "unread first",
+      EntrySort::Title => "by title",
+    }
+  }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+  /// Links follow the text they belong to
+  #[default]
+  Inline,
+  /// Links are numbered and listed below the entry
+  Footnotes,
+}
+
+/// Pane layout to use for a range of terminal widths, unset buckets use `split_view`.
+/// A layout chosen with `v` for a bucket is stored in the cache and wins over these.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+  pub narrow: Option<PaneLayout>,
+  pub medium: Option<PaneLayout>,
+  pub wide: Option<PaneLayout>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaneLayout {
+  Split,
+  Single,
+}
+
+/// Coarse terminal width classes that layout preferences are remembered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeBucket {
+  /// Less than 100 columns
+  Narrow,
+  /// 100 to 159 columns
+  Medium,
+  /// 160 columns or more
+  Wide,
+}
+
+impl SizeBucket {
+  pub fn from_width(width: u16) -> Self {
+    match width {
+      0..=99 => SizeBucket::Narrow,
+      100..=159 => SizeBucket::Medium,
+      _ => SizeBucket::Wide,
+    }
+  }
+
+  /// Name of the bucket in `[layouts]`
+  pub fn name(&self) -> &'static str {
+    match self {
+      SizeBucket::Narrow => "narrow",
+      SizeBucket::Medium => "medium",
+      SizeBucket::Wide => "wide",
+    }
+  }
+}
+
+impl UserConfig {
+  /// Directory to save entries to, expanding a leading `~`
+  pub fn save_dir(&self) -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_default();
+    match &self.save_dir {
+      Some(dir) => match dir.strip_prefix("~") {
+        Ok(rest) => home.join(rest),
+        Err(_) => dir.clone(),
+      },
+      None => dirs::document_dir().unwrap_or(home),
+    }
+  }
+
+  /// Builds the key map, failing on unknown action or key names
+  pub fn key_map(&self) -> Result<KeyMap, String> {
+    if let Some(name) = self
+      .keybindings
+      .keys()
+      .find(|name| !Action::ALL.iter().any(|a| a.name() == name.as_str()))
+    {
+      return Err(format!("Unknown action \"{}\" in [keybindings]", name));
+    }
+    let bindings = Action::ALL
+      .iter()
+      .map(|action| {
+        let keys = match self.keybindings.get(action.name()) {
+          Some(Keys::One(key)) => vec![KeyBinding::parse(key)?],
+          Some(Keys::Many(keys)) => keys
+            .iter()
+            .map(|k| KeyBinding::parse(k))
+            .collect::<Result<_, _>>()?,
+          None => action
+            .default_keys()
+            .iter()
+            .map(|k| KeyBinding::parse(k))
+            .collect::<Result<_, _>>()?,
+        };
+        Ok((*action, keys))
+      })
+      .collect::<Result<_, String>>()?;
+    Ok(KeyMap { bindings })
+  }
+
+  /// Whether to use the split view for a terminal of the given size bucket
+  pub fn split_view_for(&self, bucket: SizeBucket) -> bool {
+    let layout = match bucket {
+      SizeBucket::Narrow => self.layouts.narrow,
+      SizeBucket::Medium => self.layouts.medium,
+      SizeBucket::Wide => self.layouts.wide,
+    };
+    match layout {
+      Some(PaneLayout::Split) => true,
+      Some(PaneLayout::Single) => false,
+      None => self.split_view,
+    }
+  }
+}
+
+impl Default for UserConfig {
+  fn default() -> Self {
+    UserConfig {
+      read_count: ReadCount::default(),
+      confirm_destructive: true,
+      fetch_concurrency: 8,
+      fetch_timeout_secs: 15,
+      user_agent: None,
+      proxy: None,
+      refresh_on_launch: true,
+      refresh_interval_minutes: 0,
+      auto_open_first_unread: false,
+      mark_read_on_scroll_end: false,
+      split_view: true,
+      show_borders: true,
+      mouse: true,
+      terminal_title: true,
+      wrap_navigation: false,
+      group_by_tag: false,
+      layouts: LayoutConfig::default(),
+      link_style: LinkStyle::default(),
+      date_format: DateFormat::default(),
+      entry_columns: vec![EntryColumn::Glyph, EntryColumn::Date, EntryColumn::Title],
+      entry_sort: EntrySort::default(),
+      save_dir: None,
+      save_format: SaveFormat::default(),
+      retention_days: None,
+      max_entries_per_feed: None,
+      max_parsed_entries: None,
+      glyphs: Glyphs::default(),
+      keybindings: HashMap::new(),
+      theme: ThemeConfig::default(),
+    }
+  }
+}
+
+/// How the count column displays feeds without unread entries
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadCount {
+  /// Show "0/total"
+  #[default]
+  Full,
+  /// Show only the total number of entries
+  Total,
+  /// Leave the count empty
+  Hidden,
+}
+
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Uses `dir` instead of the default config directory, for `--config-dir`.
+/// Only takes effect before any file was looked up.
+pub fn set_config_dir(dir: PathBuf) {
+  let _ = CONFIG_DIR.set(dir);
+}
+
+/// Directory holding the config files and the cache: `--config-dir`, then
+/// `SHINBUN_CONFIG_DIR`, then shinbun in the user config directory
+fn shinbun_dir() -> &'static Path {
+  CONFIG_DIR.get_or_init(|| match std::env::var_os("SHINBUN_CONFIG_DIR") {
+    Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+    _ => config_dir()
       .expect("Config directory doesn't exist")
-      .display(),
-  );
-
-  if fs::File::open(&url_file).is_err() {
-    println!("File urls.toml not found in path: {}", &url_file);
-    exit(-1)
-  }
-
-  // Read the TOML file
-  let toml_content = fs::read_to_string(&url_file).expect("Error reading configuration file");
-
-  // Parse the TOML content into Config struct
-  let config: Config = toml::from_str(&toml_content).expect("Error parsing TOML configuration");
-  // Return the list of feeds
-  config.feeds
-}
-
-//pub fn parse_config() -> bool {
-//  let config_file = format!(
-//    "{}/shinbun/config.toml",
-//    config_dir()
-//      .expect("Config directory doesn't exist")
-//      .display(),
-//  );
-//  let toml_content = fs::read_to_string(&config_file).expect("Failed to read the config file");
-//  let config: UserConfig = toml::from_str(&toml_content).expect("Failed to parse the config");
-//  config.refresh_on_launch
-//}
+      .join("shinbun"),
+  })
+}
+
+fn urls_file() -> PathBuf {
+  shinbun_dir().join("urls.toml")
+}
+
+/// Location of the SQLite cache, kept next to the configuration files
+pub fn cache_file() -> PathBuf {
+  shinbun_dir().join("cache.db")
+}
+
+/// Feeds of urls.toml as it is now, it changes while running when feeds are added,
+/// moved or removed
+pub fn parse_feed_urls() -> Result<Vec<Feeds>, String> {
+  parse_urls_file().map(|urls| urls.feeds)
+}
+
+fn parse_urls_file() -> Result<UrlsFile, String> {
+  let url_file = urls_file();
+  let toml_content = fs::read_to_string(&url_file).map_err(|e| {
+    format!(
+      "Failed to read urls.toml from {}: {}",
+      url_file.display(),
+      e
+    )
+  })?;
+  toml::from_str(&toml_content).map_err(|e| format!("Invalid urls.toml: {}", e))
+}
+
+/// Appends a new `[[feeds]]` table to urls.toml, leaving the existing content untouched
+pub fn append_feed(feed: &Feeds) -> std::io::Result<()> {
+  let mut table = toml_edit::Table::new();
+  table.insert("link", toml_edit::value(&feed.link));
+  if let Some(name) = &feed.name {
+    table.insert("name", toml_edit::value(name));
+  }
+  if let Some(tags) = &feed.tags {
+    table.insert(
+      "tags",
+      toml_edit::value(tags.iter().collect::<toml_edit::Array>()),
+    );
+  }
+  let mut file = fs::OpenOptions::new().append(true).open(urls_file())?;
+  write!(file, "\n[[feeds]]\n{}", table)
+}
+
+/// Sets or clears the `muted` flag of the feed with the given link in urls.toml
+pub fn set_feed_muted(link: &str, muted: bool) -> std::io::Result<()> {
+  let url_file = urls_file();
+  let mut document = fs::read_to_string(&url_file)?
+    .parse::<toml_edit::DocumentMut>()
+    .map_err(std::io::Error::other)?;
+  if let Some(feeds) = document
+    .get_mut("feeds")
+    .and_then(|f| f.as_array_of_tables_mut())
+  {
+    for feed in feeds.iter_mut() {
+      if feed.get("link").and_then(|l| l.as_str()) == Some(link) {
+        if muted {
+          feed.insert("muted", toml_edit::value(true));
+        } else {
+          feed.remove("muted");
+        }
+      }
+    }
+  }
+  fs::write(&url_file, document.to_string())
+}
+
+/// Removes the `[[feeds]]` table with the given link from urls.toml
+pub fn remove_feed(link: &str) -> std::io::Result<()> {
+  let url_file = urls_file();
+  let mut document = fs::read_to_string(&url_file)?
+    .parse::<toml_edit::DocumentMut>()
+    .map_err(std::io::Error::other)?;
+  if let Some(feeds) = document
+    .get_mut("feeds")
+    .and_then(|f| f.as_array_of_tables_mut())
+  {
+    feeds.retain(|f| f.get("link").and_then(|l| l.as_str()) != Some(link));
+  }
+  fs::write(&url_file, document.to_string())
+}
+
+/// Points the `[[feeds]]` table of `link` at `new_link` in urls.toml. When `new_link` is
+/// already subscribed the table of `link` is removed instead and true is returned.
+pub fn move_feed(link: &str, new_link: &str) -> std::io::Result<bool> {
+  let url_file = urls_file();
+  let mut document = fs::read_to_string(&url_file)?
+    .parse::<toml_edit::DocumentMut>()
+    .map_err(std::io::Error::other)?;
+  let mut duplicate = false;
+  if let Some(feeds) = document
+    .get_mut("feeds")
+    .and_then(|f| f.as_array_of_tables_mut())
+  {
+    let has_link =
+      |f: &toml_edit::Table, link: &str| f.get("link").and_then(|l| l.as_str()) == Some(link);
+    duplicate = feeds.iter().any(|f| has_link(f, new_link));
+    if duplicate {
+      feeds.retain(|f| !has_link(f, link));
+    } else {
+      for feed in feeds.iter_mut().filter(|f| has_link(f, link)) {
+        feed.insert("link", toml_edit::value(new_link));
+      }
+    }
+  }
+  fs::write(&url_file, document.to_string())?;
+  Ok(duplicate)
+}
+
+/// Swaps the `[[feeds]]` tables of two links in urls.toml, along with their comments
+pub fn swap_feeds(link: &str, other: &str) -> std::io::Result<()> {
+  let url_file = urls_file();
+  let mut document = fs::read_to_string(&url_file)?
+    .parse::<toml_edit::DocumentMut>()
+    .map_err(std::io::Error::other)?;
+  if let Some(feeds) = document
+    .get_mut("feeds")
+    .and_then(|f| f.as_array_of_tables_mut())
+  {
+    let index_of = |link: &str| {
+      feeds
+        .iter()
+        .position(|f| f.get("link").and_then(|l| l.as_str()) == Some(link))
+    };
+    if let (Some(a), Some(b)) = (index_of(link), index_of(other)) {
+      let (mut table_a, mut table_b) =
+        (feeds.get(a).unwrap().clone(), feeds.get(b).unwrap().clone());
+      // Tables are written in the order of their position, not of the array
+      let (position_a, position_b) = (table_a.position(), table_b.position());
+      if let (Some(position_a), Some(position_b)) = (position_a, position_b) {
+        table_a.set_position(position_b);
+        table_b.set_position(position_a);
+      }
+      *feeds.get_mut(a).unwrap() = table_b;
+      *feeds.get_mut(b).unwrap() = table_a;
+    }
+  }
+  fs::write(&url_file, document.to_string())
+}
+
+fn parse_config() -> Result<UserConfig, String> {
+  let config_file = shinbun_dir().join("config.toml");
+  // The user config is optional, fall back to the defaults when it's missing
+  match fs::read_to_string(&config_file) {
+    Ok(toml_content) => {
+      toml::from_str(&toml_content).map_err(|e| format!("Invalid config.toml: {}", e))
+    }
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(UserConfig::default()),
+    Err(e) => Err(format!(
+      "Failed to read config.toml from {}: {}",
+      config_file.display(),
+      e
+    )),
+  }
+}
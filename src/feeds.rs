@@ -1,7 +1,18 @@
-//use config::Feeds;
-use crate::Feeds;
+use crate::{opml::unescape, query::Query, Feeds, UserConfig};
+use chrono::{DateTime, Utc};
+use encoding_rs::{Encoding, UTF_8};
 use feed_rs::parser;
-use reqwest::{get, Error as reqError};
+use futures::{stream, StreamExt};
+use regex::Regex;
+use reqwest::{
+  header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED, LOCATION,
+  },
+  redirect, Client, NoProxy, Proxy, StatusCode,
+};
+use std::{collections::HashMap, sync::OnceLock, time::Duration};
+use tokio::sync::mpsc::UnboundedSender;
 
 #[derive(Debug)]
 pub struct Feed {
@@ -9,103 +20,1108 @@ pub struct Feed {
   pub title: String,
   pub entries: Vec<FeedEntry>, // Use a custom `FeedEntry` struct with plain text content
   pub tags: Option<Vec<String>>,
+  pub muted: bool,     // Muted feeds don't contribute unread entries
+  pub position: usize, // Order of the feed in urls.toml
+  pub validators: Validators,
+  pub display_max_age_days: Option<u64>, // Hide older entries without deleting them
+  pub kind: FeedKind,
 }
 
+/// Where the entries of a feed come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeedKind {
+  /// Fetched from the feed's own URL
+  Subscription,
+  /// Copies of the entries of other feeds that match a search
+  Search(String),
+  /// Copies of the entries of other feeds that match a query from urls.toml
+  Query(Query),
+}
+
+impl Feed {
+  /// Collects the entries of `feeds` whose title or text contains `query`, ignoring case
+  pub fn search<'a>(feeds: impl Iterator<Item = &'a Feed>, query: &str) -> Feed {
+    let needle = query.to_lowercase();
+    let entries = feeds
+      .flat_map(|f| f.entries.iter())
+      .filter(|e| {
+        e.title.to_lowercase().contains(&needle)
+          || e
+            .plain_text
+            .as_deref()
+            .is_some_and(|text| text.to_lowercase().contains(&needle))
+      })
+      .cloned()
+      .collect();
+    Feed {
+      url: String::new(),
+      title: format!("Search: {}", query),
+      entries,
+      tags: None,
+      muted: false,
+      // Listed after every subscription
+      position: usize::MAX,
+      validators: Validators::default(),
+      display_max_age_days: None,
+      kind: FeedKind::Search(query.to_string()),
+    }
+  }
+}
+
+/// Response headers used to make the next request for a feed conditional
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+}
+
+/// Outcome of a successful request for a feed. `moved_to` is set when the feed was
+/// permanently redirected to another URL.
 #[derive(Debug)]
+pub enum FetchedFeed {
+  Modified {
+    body: String,
+    validators: Validators,
+    moved_to: Option<String>,
+  },
+  /// The server answered 304, the cached entries are still current
+  NotModified { moved_to: Option<String> },
+}
+
+#[derive(Debug, Clone)]
 pub struct FeedEntry {
   pub title: String,
-  pub published: Option<String>, // Optional published date
-  pub plain_text: String,        // Store preprocessed plain text here
-  pub links: Vec<String>,        // Store any relevant links
-  pub media: String,             // Store any relevant links
-}
-
-pub async fn fetch_feed(feeds: Vec<Feeds>) -> Result<Vec<String>, reqError> {
-  let mut raw_feeds: Vec<String> = Vec::new();
-  for entry in feeds {
-    match get(entry.link).await {
-      Ok(response) => match response.text().await {
-        Ok(body) => {
-          raw_feeds.push(body);
+  pub published: Option<String>,           // Optional published date
+  pub author: Option<String>,              // Names of all authors, comma separated
+  pub published_at: Option<DateTime<Utc>>, // Parsed once so relative times are cheap to redraw
+  pub plain_text: Option<String>, // Preprocessed plain text, loaded on demand from the cache
+  pub links: Vec<String>,         // Store any relevant links
+  pub enclosures: Vec<Enclosure>, // Attached media such as podcast episodes
+  pub read: bool,
+  pub starred: bool,
+  pub feed_url: String, // URL of the feed the entry was fetched from
+}
+
+/// A file attached to an entry
+#[derive(Debug, Clone)]
+pub struct Enclosure {
+  pub url: String,
+  pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnclosureKind {
+  Audio,
+  Video,
+  Image,
+  Other,
+}
+
+impl Enclosure {
+  /// Kind of the enclosure by MIME type, or by file extension when the feed leaves it out
+  pub fn kind(&self) -> EnclosureKind {
+    let mime_type = self.mime_type.as_deref().unwrap_or_default();
+    let top_level = match mime_type.split_once('/') {
+      Some((top_level, _)) => top_level.to_string(),
+      None => {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        let extension = path.rsplit_once('.').map(|(_, e)| e.to_lowercase());
+        match extension.as_deref() {
+          Some("mp3" | "m4a" | "ogg" | "oga" | "opus" | "flac" | "wav" | "aac") => "audio",
+          Some("mp4" | "m4v" | "webm" | "mkv" | "mov") => "video",
+          Some("jpg" | "jpeg" | "png" | "gif" | "webp" | "svg") => "image",
+          _ => "",
         }
-        Err(e) => {
-          eprintln!("Failed to read response body: {}", e);
+        .to_string()
+      }
+    };
+    match top_level.as_str() {
+      "audio" => EnclosureKind::Audio,
+      "video" => EnclosureKind::Video,
+      "image" => EnclosureKind::Image,
+      _ => EnclosureKind::Other,
+    }
+  }
+}
+
+/// Progress messages sent from a background refresh to the UI
+#[derive(Debug)]
+pub enum FeedUpdate {
+  /// A request for the named feed has started
+  FetchingFeed(String),
+  /// A feed was fetched and parsed, `position` is its index in urls.toml
+  UpdateFeed { position: usize, feed: Feed },
+  /// The feed hasn't changed since the last fetch and should be loaded from the cache
+  NotModified { position: usize, feed: Feeds },
+  FeedError {
+    url: String,
+    name: String,
+    error: String,
+  },
+  /// The feed at `from` permanently moved to `to`, sent before its update
+  FeedMoved {
+    position: usize,
+    from: String,
+    to: String,
+  },
+  /// Every feed of the refresh has either been updated or failed
+  FetchComplete,
+  /// A feed to subscribe to was fetched, with its body if it parsed
+  Validated {
+    feed: Feeds,
+    result: Result<String, String>,
+  },
+  /// Every feed of a bulk subscribe was fetched, in the order they were given
+  BulkValidated(Vec<(Feeds, Result<String, String>)>),
+}
+
+/// Sent unless `user_agent` is set, some hosts reject the reqwest default
+const DEFAULT_USER_AGENT: &str = concat!("shinbun/", env!("CARGO_PKG_VERSION"));
+
+/// Redirects followed for a single feed before giving up
+const MAX_REDIRECTS: usize = 10;
+
+/// HTTP client shared by every feed request
+#[derive(Debug, Clone)]
+pub struct FeedClient {
+  client: Client,
+  timeout: Duration,
+}
+
+impl FeedClient {
+  /// Builds the client from the config. Without a `proxy` there, the proxies of the
+  /// environment are used.
+  pub fn new(config: &UserConfig) -> Result<Self, String> {
+    let timeout = Duration::from_secs(config.fetch_timeout_secs);
+    let user_agent = config.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+    let mut builder = Client::builder()
+      .user_agent(user_agent)
+      .timeout(timeout)
+      .connect_timeout(timeout.min(Duration::from_secs(10)))
+      // Redirects are followed in `fetch_feed` to tell permanent moves apart
+      .redirect(redirect::Policy::none());
+    if let Some(proxy) = &config.proxy {
+      // The URL may hold credentials, so it's left out of the error
+      let url = reqwest::Url::parse(proxy)
+        .map_err(|e| format!("Invalid proxy URL in config.toml: {}", e))?;
+      let proxy = Proxy::all(url)
+        .map_err(|e| format!("Invalid proxy URL in config.toml: {}", e.without_url()))?;
+      // Setting a proxy turns off the environment's, NO_PROXY included
+      builder = builder.proxy(proxy.no_proxy(NoProxy::from_env()));
+    }
+    let client = builder
+      .build()
+      .map_err(|e| format!("Failed to build the HTTP client: {}", e))?;
+    Ok(FeedClient { client, timeout })
+  }
+
+  fn describe_error(&self, e: reqwest::Error) -> String {
+    if e.is_timeout() {
+      format!("Timed out after {}s", self.timeout.as_secs())
+    } else {
+      e.to_string()
+    }
+  }
+}
+
+/// Fetches the raw body of a single feed, the timeout applies to this request only.
+/// The request is made conditional on `validators` from the previous response.
+/// Redirects are followed, the feed counts as moved while every hop is permanent.
+/// The credentials and headers of the feed are only sent to the host of its link.
+pub async fn fetch_feed(
+  client: &FeedClient,
+  feed: &Feeds,
+  validators: &Validators,
+) -> Result<FetchedFeed, String> {
+  let mut url = reqwest::Url::parse(&feed.link).map_err(|e| format!("Invalid feed URL: {}", e))?;
+  let origin = url.origin();
+  let headers = request_headers(feed)?;
+  let mut moved_to = None;
+  let mut permanent = true;
+  let mut redirects = 0;
+  let response = loop {
+    let mut request = client.client.get(url.clone());
+    if url.origin() == origin {
+      if let Some(username) = &feed.username {
+        request = request.basic_auth(username, feed.password.as_ref().map(|p| &p.0));
+      }
+      request = request.headers(headers.clone());
+    }
+    if let Some(etag) = &validators.etag {
+      request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+      request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request
+      .send()
+      .await
+      .map_err(|e| format!("Failed to fetch feed: {}", client.describe_error(e)))?;
+    let status = response.status();
+    if !status.is_redirection() || status == StatusCode::NOT_MODIFIED {
+      break response;
+    }
+    redirects += 1;
+    if redirects > MAX_REDIRECTS {
+      return Err(format!(
+        "Failed to fetch feed: more than {} redirects",
+        MAX_REDIRECTS
+      ));
+    }
+    let location = response
+      .headers()
+      .get(LOCATION)
+      .and_then(|l| l.to_str().ok())
+      .ok_or_else(|| format!("Redirected ({}) without a location", status))?;
+    url = url
+      .join(location)
+      .map_err(|e| format!("Invalid redirect location \"{}\": {}", location, e))?;
+    permanent &= matches!(
+      status,
+      StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+    );
+    if permanent {
+      moved_to = Some(url.to_string());
+    }
+  };
+  if response.status() == StatusCode::NOT_MODIFIED {
+    return Ok(FetchedFeed::NotModified { moved_to });
+  }
+  if matches!(
+    response.status(),
+    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+  ) {
+    return Err(format!(
+      "Not allowed to read the feed ({}), check its username, password and headers",
+      response.status()
+    ));
+  }
+
+  let header = |name| {
+    response
+      .headers()
+      .get(name)
+      .and_then(|v| v.to_str().ok())
+      .map(str::to_string)
+  };
+  let validators = Validators {
+    etag: header(ETAG),
+    last_modified: header(LAST_MODIFIED),
+  };
+  let content_type = header(CONTENT_TYPE);
+  let is_html = content_type
+    .as_deref()
+    .is_some_and(|t| t.starts_with("text/html"));
+  let bytes = response
+    .bytes()
+    .await
+    .map_err(|e| format!("Failed to read response body: {}", client.describe_error(e)))?;
+  let body = decode_body(&bytes, content_type.as_deref());
+  // Some feeds are served as text/html, only pages that aren't feeds are reported
+  if is_html && parser::parse(body.as_bytes()).is_err() {
+    return Err(match discover_feeds(&body, &url).as_slice() {
+      [] => "Got a web page without a feed link instead of a feed".to_string(),
+      candidates => format!(
+        "Got a web page instead of a feed, try {}",
+        candidates.join(" or ")
+      ),
+    });
+  }
+  Ok(FetchedFeed::Modified {
+    body,
+    validators,
+    moved_to,
+  })
+}
+
+/// The extra headers of a feed, marked sensitive. Errors name the header but never
+/// its value.
+fn request_headers(feed: &Feeds) -> Result<HeaderMap, String> {
+  let mut headers = HeaderMap::new();
+  for (name, value) in &feed.headers {
+    let invalid = || format!("Invalid header \"{}\" in urls.toml", name);
+    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?;
+    let mut value = HeaderValue::from_str(&value.0).map_err(|_| invalid())?;
+    value.set_sensitive(true);
+    headers.insert(name, value);
+  }
+  Ok(headers)
+}
+
+/// Matches the encoding named in an XML declaration at the start of a document
+fn xml_encoding() -> &'static Regex {
+  static ENCODING: OnceLock<Regex> = OnceLock::new();
+  ENCODING.get_or_init(|| {
+    Regex::new(r#"^\s*<\?xml[^>]*?\sencoding\s*=\s*["']([A-Za-z0-9._:-]+)["']"#).unwrap()
+  })
+}
+
+/// Decodes a feed to UTF-8 by its byte order mark, the charset of its Content-Type
+/// or its XML declaration, in that order, falling back to UTF-8. The declaration is
+/// changed to UTF-8 too, the parser would decode the text again by it otherwise.
+fn decode_body(bytes: &[u8], content_type: Option<&str>) -> String {
+  let charset = content_type.and_then(|content_type| {
+    content_type
+      .split(';')
+      .filter_map(|param| param.trim().split_once('='))
+      .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+      .map(|(_, value)| value.trim().trim_matches('"').to_string())
+  });
+  // Declarations are ASCII in every encoding a feed is likely to use
+  let head = String::from_utf8_lossy(&bytes[..bytes.len().min(256)]);
+  let declared = xml_encoding()
+    .captures(&head)
+    .map(|captures| captures[1].to_string());
+  let encoding = charset
+    .or(declared)
+    .and_then(|label| Encoding::for_label(label.as_bytes()))
+    .unwrap_or(UTF_8);
+  // Sniffs the byte order mark itself, which wins over any label
+  let mut text = encoding.decode(bytes).0.into_owned();
+  let declaration = xml_encoding()
+    .captures(&text)
+    .and_then(|captures| captures.get(1))
+    .map(|declaration| declaration.range());
+  if let Some(declaration) = declaration {
+    text.replace_range(declaration, "UTF-8");
+  }
+  text
+}
+
+/// Feed URLs a web page advertises with `<link rel="alternate">`, resolved against `base`
+fn discover_feeds(html: &str, base: &reqwest::Url) -> Vec<String> {
+  let Ok(dom) = tl::parse(html, tl::ParserOptions::default()) else {
+    return Vec::new();
+  };
+  let mut feeds: Vec<String> = Vec::new();
+  // Tag and attribute names of HTML are case insensitive
+  let links = dom
+    .nodes()
+    .iter()
+    .filter_map(|node| node.as_tag())
+    .filter(|tag| tag.name().as_utf8_str().eq_ignore_ascii_case("link"));
+  for tag in links {
+    let attribute = |key: &str| {
+      tag
+        .attributes()
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .and_then(|(_, value)| value)
+        .map(|value| unescape(&value))
+    };
+    let is_alternate = attribute("rel").is_some_and(|rel| {
+      rel
+        .split_whitespace()
+        .any(|r| r.eq_ignore_ascii_case("alternate"))
+    });
+    let is_feed = attribute("type").is_some_and(|kind| {
+      matches!(
+        kind.trim().to_ascii_lowercase().as_str(),
+        "application/rss+xml" | "application/atom+xml" | "application/feed+json"
+      )
+    });
+    if !is_alternate || !is_feed {
+      continue;
+    }
+    if let Some(link) = attribute("href").and_then(|href| base.join(href.trim()).ok()) {
+      let link = link.to_string();
+      if !feeds.contains(&link) {
+        feeds.push(link);
+      }
+    }
+  }
+  feeds
+}
+
+/// Fetches feeds, each with its position in urls.toml, with at most `concurrency`
+/// requests in flight, reporting each one through `tx` as it completes. A failing
+/// feed only produces a `FeedError`.
+pub async fn fetch_feed_with_progress(
+  client: FeedClient,
+  feeds: Vec<(usize, Feeds)>,
+  validators: HashMap<String, Validators>,
+  area_width: usize,
+  max_entries: Option<usize>,
+  concurrency: usize,
+  tx: UnboundedSender<FeedUpdate>,
+) {
+  stream::iter(feeds)
+    .map(|(position, feed)| {
+      let tx = tx.clone();
+      let client = &client;
+      let validators = validators.get(&feed.link).cloned().unwrap_or_default();
+      async move {
+        let mut feed = feed;
+        let name = feed.name.clone().unwrap_or_else(|| feed.link.clone());
+        let _ = tx.send(FeedUpdate::FetchingFeed(name.clone()));
+        let fetched = fetch_feed(client, &feed, &validators).await;
+        if let Ok(
+          FetchedFeed::Modified {
+            moved_to: Some(to), ..
+          }
+          | FetchedFeed::NotModified { moved_to: Some(to) },
+        ) = &fetched
+        {
+          let from = std::mem::replace(&mut feed.link, to.clone());
+          let _ = tx.send(FeedUpdate::FeedMoved {
+            position,
+            from,
+            to: to.clone(),
+          });
         }
-      },
-      Err(e) => {
-        eprintln!("Failed to fetch feed: {}", e);
+        match fetched {
+          Ok(FetchedFeed::NotModified { .. }) => {
+            let _ = tx.send(FeedUpdate::NotModified { position, feed });
+          }
+          Ok(FetchedFeed::Modified {
+            body, validators, ..
+          }) => {
+            let update = match parse_feed(&body, &feed, area_width, max_entries) {
+              Ok(mut parsed) => {
+                parsed.position = position;
+                parsed.validators = validators;
+                FeedUpdate::UpdateFeed {
+                  position,
+                  feed: parsed,
+                }
+              }
+              Err(error) => FeedUpdate::FeedError {
+                url: feed.link,
+                name,
+                error,
+              },
+            };
+            let _ = tx.send(update);
+          }
+          Err(error) => {
+            let _ = tx.send(FeedUpdate::FeedError {
+              url: feed.link,
+              name,
+              error,
+            });
+          }
+        }
+      }
+    })
+    .buffer_unordered(concurrency.max(1))
+    .collect::<Vec<()>>()
+    .await;
+  let _ = tx.send(FeedUpdate::FetchComplete);
+}
+
+/// Validates `feeds` to subscribe to, `concurrency` at a time, and sends all of them
+/// once the last one is done
+pub async fn validate_feeds(
+  client: FeedClient,
+  feeds: Vec<Feeds>,
+  concurrency: usize,
+  tx: UnboundedSender<FeedUpdate>,
+) {
+  let validated = stream::iter(feeds)
+    .map(|feed| {
+      let client = &client;
+      async move {
+        let result = validate_feed(client, &feed).await;
+        (feed, result)
+      }
+    })
+    .buffered(concurrency.max(1))
+    .collect()
+    .await;
+  let _ = tx.send(FeedUpdate::BulkValidated(validated));
+}
+
+/// Fetches a single feed and checks that it parses, returning the raw body on success
+pub async fn validate_feed(client: &FeedClient, feed: &Feeds) -> Result<String, String> {
+  let FetchedFeed::Modified { body, .. } = fetch_feed(client, feed, &Validators::default()).await?
+  else {
+    return Err("Unexpected 304 Not Modified response".to_string());
+  };
+  parser::parse(body.as_bytes()).map_err(|e| format!("Failed to parse the feed: {}", e))?;
+  Ok(body)
+}
+
+/// Parses the body of `feed`, keeping its `max_entries` newest entries if set. The
+/// title comes from the config, the feed itself or its URL, in that order.
+pub fn parse_feed(
+  raw: &str,
+  feed: &Feeds,
+  area_width: usize,
+  max_entries: Option<usize>,
+) -> Result<Feed, String> {
+  let mut feed_from_xml =
+    parser::parse(raw.as_bytes()).map_err(|e| format!("Failed to parse the feed: {}", e))?;
+  // Before their text is rendered, which is most of the work for a long feed
+  if let Some(max) = max_entries.filter(|&max| max > 0) {
+    feed_from_xml.entries = newest_entries(feed_from_xml.entries, max);
+  }
+
+  let title = feed
+    .name
+    .clone()
+    .or_else(|| {
+      feed_from_xml
+        .title
+        .map(|t| t.content.trim().to_string())
+        .filter(|t| !t.is_empty())
+    })
+    .unwrap_or_else(|| feed.link.clone());
+
+  let mut entries: Vec<FeedEntry> = Vec::new();
+
+  for entry in feed_from_xml.entries {
+    // Convert the content to plain text once, falling back to the summary
+    let plain_text = match (entry.content.as_ref(), entry.summary.as_ref()) {
+      (Some(content), _) if content.body.is_some() => render_text(
+        content.body.as_deref().unwrap_or_default(),
+        content.content_type.as_ref(),
+        area_width,
+      ),
+      (_, Some(summary)) => {
+        render_text(&summary.content, summary.content_type.as_ref(), area_width)
       }
+      _ => String::new(),
+    };
+
+    // Collect links or other metadata
+    let links = entry.links.iter().map(|l| l.href.clone()).collect();
+    let enclosures = entry
+      .media
+      .iter()
+      .flat_map(|media| media.content.iter())
+      .filter_map(|content| {
+        Some(Enclosure {
+          url: content.url.as_ref()?.to_string(),
+          mime_type: content.content_type.as_ref().map(|m| m.to_string()),
+        })
+      })
+      .collect();
+
+    let authors = entry
+      .authors
+      .iter()
+      .filter_map(author_name)
+      .collect::<Vec<_>>();
+
+    let feed_entry = FeedEntry {
+      title: entry
+        .title
+        .map(|t| t.content)
+        .filter(|t| !t.trim().is_empty())
+        .or_else(|| text_snippet(&plain_text))
+        .unwrap_or_else(|| "No title".to_string()),
+      published: entry.published.map(|p| p.to_string()),
+      author: (!authors.is_empty()).then(|| authors.join(", ")),
+      published_at: entry.published,
+      plain_text: Some(plain_text), // Store preprocessed plain text
+      links,
+      enclosures,
+      read: false,
+      starred: false,
+      feed_url: feed.link.clone(),
+    };
+
+    entries.push(feed_entry);
+  }
+
+  Ok(Feed {
+    url: feed.link.clone(),
+    title,
+    entries,
+    tags: feed.tags.clone(),
+    muted: feed.muted,
+    position: 0, // Set by the caller, who knows the index in urls.toml
+    validators: Validators::default(),
+    display_max_age_days: feed.display_max_age_days,
+    kind: FeedKind::Subscription,
+  })
+}
+
+/// The `max` most recently published entries in their order in the feed. Undated
+/// entries count as the oldest, in the order they're listed.
+fn newest_entries(entries: Vec<feed_rs::model::Entry>, max: usize) -> Vec<feed_rs::model::Entry> {
+  if entries.len() <= max {
+    return entries;
+  }
+  let mut newest: Vec<usize> = (0..entries.len()).collect();
+  newest.sort_by_key(|&i| std::cmp::Reverse(entries[i].published));
+  let mut keep = vec![false; entries.len()];
+  for i in newest.into_iter().take(max) {
+    keep[i] = true;
+  }
+  entries
+    .into_iter()
+    .zip(keep)
+    .filter_map(|(entry, keep)| keep.then_some(entry))
+    .collect()
+}
+
+/// Longest title made up from the text of an entry that has none
+const SNIPPET_CHARS: usize = 60;
+
+/// Start of the text on one line for entries without a title, like posts of link
+/// blogs and microblogs. It becomes the title that's stored, so it can't depend on
+/// how the text was wrapped.
+fn text_snippet(plain_text: &str) -> Option<String> {
+  let mut words = plain_text.split_whitespace();
+  let mut snippet = words.next()?.to_string();
+  for word in words {
+    snippet.push(' ');
+    snippet.push_str(word);
+    if snippet.chars().count() > SNIPPET_CHARS {
+      break;
     }
   }
-  Ok::<Vec<String>, reqError>(raw_feeds)
+  if snippet.chars().count() > SNIPPET_CHARS {
+    snippet = snippet.chars().take(SNIPPET_CHARS - 1).collect::<String>();
+    snippet.truncate(snippet.trim_end().len());
+    snippet.push('…');
+  }
+  Some(snippet)
+}
+
+/// Renders entry content as wrapped plain text, leaving room for the entry view's
+/// padding. HTML entities are decoded and scripts and styles dropped.
+fn render_text(body: &str, content_type: &str, area_width: usize) -> String {
+  if content_type.starts_with("text/plain") {
+    return body.trim().to_string();
+  }
+  let width = area_width.saturating_sub(15).max(20);
+  match html2text::config::plain().lines_from_read(body.as_bytes(), width) {
+    Ok(lines) => lines
+      .into_iter()
+      .map(|line| line.chars().collect::<String>())
+      .collect::<Vec<String>>()
+      .join("\n"),
+    Err(_) => body.to_string(),
+  }
 }
 
-pub fn parse_feed(links: Vec<String>, feeds: Vec<Feeds>, area_width: usize) -> Vec<Feed> {
-  let mut all_feeds: Vec<Feed> = Vec::new();
+/// Display name of an entry author. feed-rs names RSS `<author>` elements "author" and
+/// keeps their "email (Name)" text as the email.
+fn author_name(person: &feed_rs::model::Person) -> Option<String> {
+  let name = person.name.trim();
+  match person.email.as_deref().map(str::trim) {
+    Some(email) if name == "author" => {
+      let display = email
+        .split_once('(')
+        .and_then(|(_, rest)| rest.strip_suffix(')'))
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .unwrap_or(email);
+      (!display.is_empty()).then(|| display.to_string())
+    }
+    _ => (!name.is_empty()).then(|| name.to_string()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::Secret;
+  use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+  };
+
+  const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"><channel><title>Test feed</title><link>http://example.com/</link>
+<item><title>First post</title><link>http://example.com/1</link>
+<description>Hello</description></item>
+</channel></rss>"#;
 
-  for (index, raw) in links.into_iter().enumerate() {
-    let feed_from_xml = match parser::parse(raw.as_bytes()) {
-      Ok(feed) => feed,
-      Err(e) => {
-        eprintln!("Failed to parse the feed: {}", feeds[index].link);
-        eprintln!("Details: {}", e);
-        std::process::exit(-1);
+  /// A 200 response with `headers`, each ending in CRLF, and `body`
+  fn response(headers: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+      "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+      body.len(),
+      headers
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+  }
+
+  /// Answers a single request on a local port with `response`. Returns the URL of the
+  /// feed and a handle yielding the request head it got, lowercased.
+  fn serve_once(response: Vec<u8>) -> (String, thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/feed.xml", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut request = Vec::new();
+      let mut buf = [0; 1024];
+      while !request.ends_with(b"\r\n\r\n") {
+        let read = stream.read(&mut buf).unwrap();
+        if read == 0 {
+          break;
+        }
+        request.extend_from_slice(&buf[..read]);
       }
+      stream.write_all(&response).unwrap();
+      String::from_utf8_lossy(&request).to_lowercase()
+    });
+    (url, handle)
+  }
+
+  fn feed(link: &str) -> Feeds {
+    Feeds {
+      link: link.to_string(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn decodes_entities_and_breaks_paragraphs_and_lines() {
+    let html = "<p>Fish &amp; chips &mdash; &quot;tasty&quot; &#x263A;</p>\
+      <p>Line one<br>Line two<br/>Line three</p>";
+    assert_eq!(
+      render_text(html, "text/html", 80),
+      "Fish & chips — \"tasty\" ☺\n\nLine one\nLine two\nLine three"
+    );
+  }
+
+  #[test]
+  fn drops_scripts_and_styles() {
+    let html = r#"<p>Before</p><script type="text/javascript">var tracker = "x";</script>
+      <style>.post { color: red }</style><p>After</p>"#;
+    let text = render_text(html, "text/html", 80);
+    assert!(
+      !text.contains("tracker") && !text.contains("color"),
+      "{}",
+      text
+    );
+    assert!(text.contains("Before") && text.contains("After"));
+  }
+
+  #[test]
+  fn keeps_link_text() {
+    let html = r#"<p>Read <a href="http://example.com/more">the rest</a> here</p>"#;
+    let text = render_text(html, "text/html", 80);
+    assert!(text.starts_with("Read [the rest][1] here"), "{}", text);
+    assert!(text.contains("http://example.com/more"));
+  }
+
+  #[test]
+  fn survives_unclosed_tags() {
+    let text = render_text("<p>unclosed <b>bold <i>italic</p><p>next", "text/html", 80);
+    assert!(
+      text.contains("unclosed bold") && text.contains("next"),
+      "{}",
+      text
+    );
+  }
+
+  #[test]
+  fn leaves_plain_text_alone() {
+    assert_eq!(
+      render_text("  plain &amp; text  ", "text/plain", 80),
+      "plain &amp; text"
+    );
+  }
+
+  #[test]
+  fn parsed_entries_get_clean_text() {
+    let rss = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Blog</title>
+<item><title>Post</title><description><![CDATA[<div class="entry"><p>Hello &amp; welcome</p>
+<script>track()</script><p>Bye</p></div>]]></description></item></channel></rss>"#;
+    let parsed = parse_feed(rss, &feed("http://example.com/feed.xml"), 80, None).unwrap();
+    let text = parsed.entries[0].plain_text.as_deref().unwrap();
+    assert_eq!(text, "Hello & welcome\n\nBye");
+  }
+
+  const MALFORMED: &str = "<?xml version=\"1.0\"?><rss version=\"2.0\"><channel><title>Broken\
+    <item><title>Never closed</item></rss";
+
+  #[test]
+  fn malformed_feeds_are_errors() {
+    let error = parse_feed(MALFORMED, &feed("http://example.com/feed.xml"), 80, None).unwrap_err();
+    assert!(error.starts_with("Failed to parse the feed"), "{}", error);
+  }
+
+  #[test]
+  fn feeds_without_a_title_use_the_name_or_url() {
+    let rss = r#"<rss version="2.0"><channel><item><title>Post</title></item></channel></rss>"#;
+    let url = "http://example.com/feed.xml";
+    assert_eq!(parse_feed(rss, &feed(url), 80, None).unwrap().title, url);
+    let named = Feeds {
+      name: Some("Named".to_string()),
+      ..feed(url)
     };
+    assert_eq!(parse_feed(rss, &named, 80, None).unwrap().title, "Named");
+  }
 
-    let title = feeds[index]
-      .name
-      .clone()
-      .unwrap_or_else(|| feed_from_xml.title.unwrap().content);
-
-    let mut entries: Vec<FeedEntry> = Vec::new();
-
-    for entry in feed_from_xml.entries {
-      // Convert HTML content to plain text once
-      let main_content = entry
-        .content
-        .as_ref()
-        .and_then(|c| c.body.clone()) // Extract the HTML content
-        .unwrap_or_else(|| "".to_string()); // Use empty string if none
-
-      // Use the dynamic width from the area
-      let plain_text = html2text::config::plain()
-        .lines_from_read(main_content.as_bytes(), area_width - 15)
-        .expect("Failed to parse HTML")
+  #[test]
+  fn entries_without_a_title_get_one_from_their_text() {
+    let rss = r#"<rss version="2.0"><channel><title>Microblog</title>
+<item><description>Short note</description></item>
+<item><title>  </title><description>A much longer post that goes on well past the sixty characters a title may take</description></item>
+<item></item>
+</channel></rss>"#;
+    let parsed = parse_feed(rss, &feed("http://example.com/feed.xml"), 80, None).unwrap();
+    let titles: Vec<&str> = parsed.entries.iter().map(|e| e.title.as_str()).collect();
+    assert_eq!(
+      titles,
+      [
+        "Short note",
+        "A much longer post that goes on well past the sixty charact…",
+        "No title"
+      ]
+    );
+    assert_eq!(titles[1].chars().count(), SNIPPET_CHARS);
+  }
+
+  #[test]
+  fn oversized_feeds_keep_their_newest_entries_in_feed_order() {
+    let items: String = [
+      ("Tuesday", Some("Tue, 02 Jan 2024 00:00:00 GMT")),
+      ("Undated", None),
+      ("Thursday", Some("Thu, 04 Jan 2024 00:00:00 GMT")),
+      ("Monday", Some("Mon, 01 Jan 2024 00:00:00 GMT")),
+      ("Wednesday", Some("Wed, 03 Jan 2024 00:00:00 GMT")),
+    ]
+    .iter()
+    .map(|(title, date)| {
+      let date = date.map_or(String::new(), |d| format!("<pubDate>{}</pubDate>", d));
+      format!("<item><title>{}</title>{}</item>", title, date)
+    })
+    .collect();
+    let rss = format!(
+      r#"<rss version="2.0"><channel><title>Week</title>{}</channel></rss>"#,
+      items
+    );
+    let titles = |max_entries| {
+      parse_feed(&rss, &feed("http://example.com/feed.xml"), 80, max_entries)
+        .unwrap()
+        .entries
         .into_iter()
-        .map(|line| line.chars().collect::<String>())
-        .collect::<Vec<String>>()
-        .join("\n");
-
-      // Collect links or other metadata
-      let links = entry.links.iter().map(|l| l.href.clone()).collect();
-      let media = entry
-        .media
-        .first()
-        .and_then(|media| media.content.first())
-        .map(|content_item| content_item.url.as_ref().map(|l| l.to_string()))
-        .unwrap_or_default()
-        .unwrap_or_default();
-
-      let feed_entry = FeedEntry {
-        title: entry.title.map_or("No title".to_string(), |t| t.content),
-        published: entry.published.map(|p| p.to_string()),
-        plain_text, // Store preprocessed plain text
-        links,
-        media,
-      };
-
-      entries.push(feed_entry);
-    }
-
-    let feed = Feed {
-      url: feeds[index].link.clone(),
-      title,
-      entries,
-      tags: feeds[index].tags.clone(),
+        .map(|e| e.title)
+        .collect::<Vec<_>>()
     };
+    assert_eq!(titles(Some(3)), ["Tuesday", "Thursday", "Wednesday"]);
+    assert_eq!(titles(Some(5)).len(), 5);
+    assert_eq!(titles(Some(0)).len(), 5);
+    assert_eq!(titles(None).len(), 5);
+  }
+
+  #[test]
+  fn decodes_windows_1252_by_the_content_type_or_the_declaration() {
+    let body = b"<?xml version=\"1.0\" encoding=\"windows-1252\"?><rss version=\"2.0\"><channel><title>Caf\xe9</title></channel></rss>";
+    let decoded = decode_body(body, Some("text/xml; charset=\"Windows-1252\""));
+    assert!(decoded.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+    assert!(decoded.contains("Café"));
+    assert_eq!(decode_body(body, None), decoded);
+    // The header wins over a declaration that names another encoding
+    let mislabeled = decode_body(
+      &[
+        b"<?xml version=\"1.0\" encoding=\"utf-8\"?>".as_slice(),
+        b"Caf\xe9",
+      ]
+      .concat(),
+      Some("application/rss+xml;charset=cp1252"),
+    );
+    assert!(mislabeled.ends_with("Café"));
+    let parsed = parse_feed(&decoded, &feed("http://example.com/feed.xml"), 80, None);
+    assert_eq!(parsed.unwrap().title, "Café");
+  }
+
+  #[test]
+  fn decodes_shift_jis_by_the_declaration() {
+    let xml = r#"<?xml version="1.0" encoding="Shift_JIS"?><rss version="2.0"><channel><title>日本語</title><item><title>ニュース</title></item></channel></rss>"#;
+    let (body, _, unmappable) = encoding_rs::SHIFT_JIS.encode(xml);
+    assert!(!unmappable);
+    let decoded = decode_body(&body, Some("application/xml"));
+    assert_eq!(decoded, xml.replace("Shift_JIS", "UTF-8"));
+    let parsed = parse_feed(&decoded, &feed("http://example.com/feed.xml"), 80, None).unwrap();
+    assert_eq!(parsed.title, "日本語");
+    assert_eq!(parsed.entries[0].title, "ニュース");
+  }
 
-    all_feeds.push(feed);
+  #[test]
+  fn undeclared_bodies_are_utf_8() {
+    let body = "<rss><channel><title>Café</title></channel></rss>";
+    assert_eq!(decode_body(body.as_bytes(), None), body);
+    assert_eq!(
+      decode_body(body.as_bytes(), Some("text/xml; charset=bogus")),
+      body
+    );
+  }
+
+  #[tokio::test]
+  async fn a_malformed_feed_is_reported_and_the_refresh_completes() {
+    let (url, _) = serve_once(response("", MALFORMED.as_bytes()));
+    let client = FeedClient::new(&UserConfig::default()).unwrap();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let feeds = vec![(0, feed(&url))];
+    fetch_feed_with_progress(client, feeds, HashMap::new(), 80, None, 1, tx).await;
+    assert!(matches!(rx.recv().await, Some(FeedUpdate::FetchingFeed(_))));
+    match rx.recv().await {
+      Some(FeedUpdate::FeedError {
+        url: failed, error, ..
+      }) => {
+        assert_eq!(failed, url);
+        assert!(error.starts_with("Failed to parse the feed"), "{}", error);
+      }
+      update => panic!("Expected a feed error, got {:?}", update),
+    }
+    assert!(matches!(rx.recv().await, Some(FeedUpdate::FetchComplete)));
+  }
+
+  #[tokio::test]
+  async fn parses_a_gzip_encoded_feed() {
+    use flate2::{write::GzEncoder, Compression};
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(RSS.as_bytes()).unwrap();
+    let body = encoder.finish().unwrap();
+    let (url, request) = serve_once(response(
+      "Content-Type: application/rss+xml\r\nContent-Encoding: gzip\r\n",
+      &body,
+    ));
+    let client = FeedClient::new(&UserConfig::default()).unwrap();
+    let body = validate_feed(&client, &feed(&url)).await.unwrap();
+    let parsed = parse_feed(&body, &feed(&url), 80, None).unwrap();
+    assert_eq!(parsed.title, "Test feed");
+    assert_eq!(parsed.entries[0].title, "First post");
+    let request = request.join().unwrap();
+    let accepted = request
+      .lines()
+      .find_map(|line| line.strip_prefix("accept-encoding: "))
+      .unwrap_or_default();
+    for encoding in ["gzip", "br", "deflate"] {
+      assert!(accepted.contains(encoding), "{}", accepted);
+    }
+  }
+
+  #[tokio::test]
+  async fn sends_the_default_user_agent() {
+    let (url, request) = serve_once(response("", RSS.as_bytes()));
+    let client = FeedClient::new(&UserConfig::default()).unwrap();
+    validate_feed(&client, &feed(&url)).await.unwrap();
+    let request = request.join().unwrap();
+    let expected = format!("user-agent: {}\r\n", DEFAULT_USER_AGENT.to_lowercase());
+    assert!(request.contains(&expected), "{}", request);
+  }
+
+  #[tokio::test]
+  async fn sends_the_configured_user_agent() {
+    let (url, request) = serve_once(response("", RSS.as_bytes()));
+    let config = UserConfig {
+      user_agent: Some("Mozilla/5.0 (compatible; test)".to_string()),
+      ..Default::default()
+    };
+    let client = FeedClient::new(&config).unwrap();
+    validate_feed(&client, &feed(&url)).await.unwrap();
+    let request = request.join().unwrap();
+    assert!(request.contains("user-agent: mozilla/5.0 (compatible; test)\r\n"));
+  }
+
+  fn private_feed(url: &str) -> Feeds {
+    Feeds {
+      username: Some("me".to_string()),
+      password: Some(Secret("secret".to_string())),
+      headers: HashMap::from([("X-Api-Key".to_string(), Secret("key123".to_string()))]),
+      ..feed(url)
+    }
+  }
+
+  #[tokio::test]
+  async fn sends_the_credentials_and_headers_of_the_feed() {
+    let (url, request) = serve_once(response("", RSS.as_bytes()));
+    let client = FeedClient::new(&UserConfig::default()).unwrap();
+    fetch_feed(&client, &private_feed(&url), &Validators::default())
+      .await
+      .unwrap();
+    let request = request.join().unwrap();
+    // base64 of "me:secret"
+    assert!(request.contains("authorization: basic bwu6c2vjcmv0\r\n"));
+    assert!(request.contains("x-api-key: key123\r\n"));
+  }
+
+  #[tokio::test]
+  async fn credentials_are_not_sent_to_other_hosts_after_a_redirect() {
+    let (moved, moved_request) = serve_once(response("", RSS.as_bytes()));
+    let redirect = format!(
+      "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+      moved
+    );
+    let (url, request) = serve_once(redirect.into_bytes());
+    let client = FeedClient::new(&UserConfig::default()).unwrap();
+    fetch_feed(&client, &private_feed(&url), &Validators::default())
+      .await
+      .unwrap();
+    assert!(request.join().unwrap().contains("authorization: basic"));
+    let moved_request = moved_request.join().unwrap();
+    assert!(!moved_request.contains("authorization"));
+    assert!(!moved_request.contains("x-api-key"));
+  }
+
+  #[tokio::test]
+  async fn refused_credentials_are_reported_without_them() {
+    let unauthorized =
+      b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    let (url, _) = serve_once(unauthorized.to_vec());
+    let client = FeedClient::new(&UserConfig::default()).unwrap();
+    let error = fetch_feed(&client, &private_feed(&url), &Validators::default())
+      .await
+      .err()
+      .unwrap();
+    assert!(error.contains("401"), "{}", error);
+    assert!(
+      !error.contains("secret") && !error.contains("key123"),
+      "{}",
+      error
+    );
+  }
+
+  #[tokio::test]
+  async fn fetches_through_the_configured_proxy() {
+    let (proxy, request) = serve_once(response("", RSS.as_bytes()));
+    let config = UserConfig {
+      proxy: Some(proxy.trim_end_matches("/feed.xml").to_string()),
+      ..Default::default()
+    };
+    let client = FeedClient::new(&config).unwrap();
+    validate_feed(&client, &feed("http://example.invalid/feed.xml"))
+      .await
+      .unwrap();
+    let request = request.join().unwrap();
+    assert!(request.starts_with("get http://example.invalid/feed.xml http/1.1\r\n"));
+  }
+
+  #[tokio::test]
+  async fn hosts_in_no_proxy_are_fetched_directly() {
+    std::env::set_var("NO_PROXY", "127.0.0.1");
+    // Nothing listens on the proxy's port once the listener is dropped
+    let unused = TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap();
+    let (url, request) = serve_once(response("", RSS.as_bytes()));
+    let config = UserConfig {
+      proxy: Some(format!("http://{}", unused)),
+      ..Default::default()
+    };
+    let client = FeedClient::new(&config).unwrap();
+    validate_feed(&client, &feed(&url)).await.unwrap();
+    assert!(request
+      .join()
+      .unwrap()
+      .starts_with("get /feed.xml http/1.1\r\n"));
+  }
+
+  #[test]
+  fn invalid_proxy_urls_are_reported_without_them() {
+    let config = UserConfig {
+      proxy: Some("http://me:hunter2@[::1".to_string()),
+      ..Default::default()
+    };
+    let error = FeedClient::new(&config).err().unwrap();
+    assert!(
+      error.starts_with("Invalid proxy URL in config.toml"),
+      "{}",
+      error
+    );
+    assert!(!error.contains("hunter2"), "{}", error);
   }
-  all_feeds
 }
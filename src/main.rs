@@ -1,31 +1,262 @@
-use config::Feeds;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
-use feeds::Feed;
+use cache::{FeedCache, FeedHealth};
+use chrono::{DateTime, Utc};
+use config::{
+  Action, Config, DateFormat, EntryColumn, EntrySort, Feeds, KeyMap, LinkStyle, QueryFeed,
+  ReadCount, SizeBucket, UserConfig,
+};
+use crossterm::{
+  event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+    MouseEventKind,
+  },
+  execute,
+  terminal::SetTitle,
+};
+use feeds::{Enclosure, EnclosureKind, Feed, FeedClient, FeedEntry, FeedKind, FeedUpdate};
 use ratatui::{
   prelude::*,
   symbols::border,
   widgets::{block::*, *},
 };
 
-use std::io;
+use query::{Query, QueryFilter};
+use regex::{Regex, RegexBuilder};
+use std::{
+  cell::{Cell, RefCell, RefMut},
+  cmp::Reverse,
+  collections::{HashMap, HashSet},
+  io,
+  sync::OnceLock,
+  time::{Duration, Instant},
+};
+use theme::Theme;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// How often the screen is redrawn while idle, keeping relative times current
+const TICK_RATE: Duration = Duration::from_secs(1);
+/// Faster redraw rate while feeds are loading so the spinner animates
+const LOADING_TICK_RATE: Duration = Duration::from_millis(100);
+/// How long the loading summary stays on screen after a refresh finished
+const LOADING_SUMMARY_DURATION: Duration = Duration::from_secs(3);
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+/// How long to wait for another digit before jumping to a typed entry number
+const NUMBER_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a notice like "Link copied" stays in the status bar
+const NOTICE_DURATION: Duration = Duration::from_secs(2);
+/// First row of the tag sidebar, listing feeds regardless of their tags
+const ALL_TAGS: &str = "All feeds";
+/// Columns an unwrapped entry scrolls sideways per press of Left or Right
+const HORIZONTAL_SCROLL_COLUMNS: usize = 4;
+/// Lines the open entry scrolls per turn of the mouse wheel
+const MOUSE_SCROLL_LINES: usize = 3;
+/// Feeds that failed this many fetches in a row are flagged in the feeds list
+const FAILING_FEED_FAILURES: u32 = 3;
+/// Source and author columns of the entries list are cut off beyond this width
+const ENTRY_COLUMN_MAX_WIDTH: usize = 20;
 
+mod cache;
 mod config;
 mod feeds;
+mod opml;
+mod query;
+mod save;
+mod theme;
 mod ui;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-  let mut terminal = ui::init()?;
-  let area_width = terminal.size()?.width as usize;
+  let mut args: Vec<String> = std::env::args().collect();
+  if let Some(flag) = args.iter().position(|a| a == "--config-dir") {
+    let Some(dir) = args.get(flag + 1) else {
+      eprintln!("Usage: shinbun --config-dir <directory> [command]");
+      std::process::exit(1);
+    };
+    config::set_config_dir(dir.into());
+    args.drain(flag..flag + 2);
+  }
+  match args.get(1).map(String::as_str) {
+    Some("--import-newsboat") => {
+      let Some(path) = args.get(2) else {
+        eprintln!("Usage: shinbun --import-newsboat <path to cache.db>");
+        std::process::exit(1);
+      };
+      let cache = open_cache();
+      match cache.import_newsboat_read_state(path.as_ref()) {
+        Ok(matched) => println!("Marked {} entries as read from {}", matched, path),
+        Err(e) => {
+          eprintln!("Failed to import read state from {}: {}", path, e);
+          std::process::exit(1);
+        }
+      }
+      return Ok(());
+    }
+    Some("--compact-db") => {
+      let cache = open_cache();
+      let result = cache.stats().and_then(|before| {
+        cache.compact()?;
+        Ok((before, cache.stats()?))
+      });
+      match result {
+        Ok((before, after)) => println!(
+          "Compacted the cache from {} to {}: {} feeds, {} entries, {} unread",
+          format_size(before.size),
+          format_size(after.size),
+          after.feeds,
+          after.entries,
+          after.unread
+        ),
+        Err(e) => {
+          eprintln!("Failed to compact the cache: {}", e);
+          std::process::exit(1);
+        }
+      }
+      return Ok(());
+    }
+    Some("--list-feeds") => {
+      let feeds = config::parse_feed_urls().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+      });
+      // Tab separated so scripts can split the columns
+      for feed in feeds {
+        println!(
+          "{}\t{}\t{}",
+          feed.name.as_deref().unwrap_or_default(),
+          feed.link,
+          feed.tags.unwrap_or_default().join(",")
+        );
+      }
+      return Ok(());
+    }
+    Some("--refresh") => {
+      let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+      });
+      if !refresh_headless(config).await {
+        std::process::exit(1);
+      }
+      return Ok(());
+    }
+    Some("--import-opml") => {
+      let Some(path) = args.get(2) else {
+        eprintln!("Usage: shinbun --import-opml <file.opml>");
+        std::process::exit(1);
+      };
+      match opml::import_opml(path.as_ref()) {
+        Ok((imported, skipped)) => println!(
+          "Imported {} feeds from {}, skipped {} already subscribed",
+          imported, path, skipped
+        ),
+        Err(e) => {
+          eprintln!("Failed to import feeds from {}: {}", path, e);
+          std::process::exit(1);
+        }
+      }
+      return Ok(());
+    }
+    Some("--export-json") => {
+      let Some(path) = args.get(2) else {
+        eprintln!("Usage: shinbun --export-json <file.json or - for stdout>");
+        std::process::exit(1);
+      };
+      let cache = open_cache();
+      let result = if path == "-" {
+        cache.export_json(io::stdout().lock())
+      } else {
+        std::fs::File::create(path)
+          .and_then(|file| cache.export_json(io::BufWriter::new(file)))
+          .inspect(|(feeds, entries)| {
+            println!(
+              "Exported {} entries of {} feeds to {}",
+              entries, feeds, path
+            )
+          })
+      };
+      if let Err(e) = result {
+        eprintln!("Failed to export the cache to {}: {}", path, e);
+        std::process::exit(1);
+      }
+      return Ok(());
+    }
+    Some("--import-json") => {
+      let Some(path) = args.get(2) else {
+        eprintln!("Usage: shinbun --import-json <file.json or - for stdin>");
+        std::process::exit(1);
+      };
+      let mut cache = open_cache();
+      let result = if path == "-" {
+        cache.import_json(io::stdin().lock())
+      } else {
+        std::fs::File::open(path).and_then(|file| cache.import_json(io::BufReader::new(file)))
+      };
+      match result {
+        Ok((feeds, entries)) => {
+          println!(
+            "Imported {} entries of {} feeds from {}",
+            entries, feeds, path
+          )
+        }
+        Err(e) => {
+          eprintln!("Failed to import {}: {}", path, e);
+          std::process::exit(1);
+        }
+      }
+      return Ok(());
+    }
+    Some("--export-opml") => {
+      let Some(path) = args.get(2) else {
+        eprintln!("Usage: shinbun --export-opml <file.opml>");
+        std::process::exit(1);
+      };
+      match opml::export_opml(path.as_ref()) {
+        Ok(exported) => println!("Exported {} feeds to {}", exported, path),
+        Err(e) => {
+          eprintln!("Failed to export feeds to {}: {}", path, e);
+          std::process::exit(1);
+        }
+      }
+      return Ok(());
+    }
+    _ => {}
+  }
+
+  // Report configuration errors before the terminal is taken over
+  let Config {
+    settings: user_config,
+    feeds,
+    queries,
+  } = Config::load().unwrap_or_else(|e| {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  });
+  let key_map = user_config.key_map().unwrap_or_else(|e| {
+    eprintln!("Invalid config.toml: {}", e);
+    std::process::exit(1);
+  });
+  let theme = user_config.theme.resolve().unwrap_or_else(|e| {
+    eprintln!("Invalid config.toml: {}", e);
+    std::process::exit(1);
+  });
+  let client = FeedClient::new(&user_config).unwrap_or_else(|e| {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  });
+  let cache = open_cache();
 
-  let feeds_urls = config::parse_feed_urls;
-  let xml = feeds::fetch_feed(feeds_urls()).await;
-  //let list: Vec<Feed> = feeds::parse_feed(xml.expect("Failed to fetch feed"), feeds_urls());
+  let terminal_title = user_config.terminal_title;
+  let mut terminal = ui::init(user_config.mouse, terminal_title)?;
+  let area_width = terminal.size()?.width as usize;
 
-  let list: Vec<Feed> =
-    feeds::parse_feed(xml.expect("Failed to fetch feed"), feeds_urls(), area_width);
-  let app = App::new(list).run(&mut terminal);
-  ui::restore()?;
+  let mut app = App::new(area_width, user_config, key_map, theme, cache, client);
+  app.load_cached_feeds(&feeds);
+  app.load_query_feeds(&queries);
+  if app.config.refresh_on_launch {
+    app.fetch_feeds(feeds);
+  }
+  let app = app.run(&mut terminal);
+  ui::restore(terminal_title)?;
   app
 }
 
@@ -39,20 +270,252 @@ pub struct App {
   entry_open: bool,
   scroll: usize,
   _scroll_state: ScrollbarState,
+  // Lines of the open entry that fit on screen and how far it scrolls, updated on render
+  entry_height: Cell<usize>,
+  max_scroll: Cell<usize>,
+  // Furthest the open entry was scrolled, saved when it's closed to resume from there
+  furthest_scroll: Cell<usize>,
+  // Columns the open entry is scrolled sideways while its lines aren't wrapped, and
+  // how far they reach past the screen
+  horizontal_scroll: usize,
+  max_horizontal_scroll: Cell<usize>,
+  // Link of the open entry's body picked with Tab, opened with Enter
+  focused_link: Option<usize>,
+  // Where the rows of the lists were drawn and their first visible row, for mouse clicks
+  tags_list_area: Cell<(Rect, usize)>,
+  feeds_list_area: Cell<(Rect, usize)>,
+  entries_list_area: Cell<(Rect, usize)>,
+  // Wrapping a long entry is slow, so it's only laid out again when it changes
+  entry_view: RefCell<Option<EntryView>>,
+  area_width: usize,
+  config: UserConfig,
+  key_map: KeyMap,
+  theme: Theme,
+  split_view: bool,
+  show_borders: bool,
+  // List links as numbered references below the entry instead of inline
+  footnote_links: bool,
+  // Wrap long lines of the entry view, off for preformatted text like code
+  wrap_lines: bool,
+  // Hide read entries and feeds without unread entries
+  show_unread_only: bool,
+  // Sidebar of feed tags, opened with `t`
+  show_tags: bool,
+  // Only feeds with this tag are listed, picked in the tag sidebar
+  tag_filter: Option<String>,
+  // Entries of the open feed read since entering it, listed until the feed is left
+  read_in_view: Vec<usize>,
+  // Entries the last mark of a feed or everything as read changed, for undoing it
+  marked_read: Vec<(String, String, Option<String>)>,
+  // Entries the last refresh brought in, by `entry_key`, until they're opened
+  new_entries: HashSet<(String, String, Option<String>)>,
+  entry_sort: EntrySort,
+  // Row of the entries list selected when each feed was last left, by `selection_key`
+  entry_selections: HashMap<String, usize>,
+  // Where the last session was left, until its feed shows up or a key is pressed
+  last_selection: Option<LastSelection>,
+  // Size bucket the current layout was chosen for
+  size_bucket: Option<SizeBucket>,
+  cache: FeedCache,
+  client: FeedClient,
+  // Background refreshes report back through this channel
+  updates_tx: UnboundedSender<FeedUpdate>,
+  updates: UnboundedReceiver<FeedUpdate>,
+  last_refresh: Instant,
+  loading_state: LoadingState,
+  // Feeds that failed during the last refresh, with the reason
+  feed_errors: Vec<(String, String)>,
+  // Why the last fetch of a feed failed, by URL, until it's fetched again
+  fetch_errors: HashMap<String, String>,
+  // When each feed was last fetched or found unchanged, by URL
+  fetched_at: HashMap<String, DateTime<Utc>>,
+  // How fetching each stored feed has gone, by URL, as of the last refresh
+  feed_health: HashMap<String, FeedHealth>,
+  // Unread count last put in the terminal title
+  title_unread: Option<usize>,
+  // Scroll of the diagnostics popup, `None` while it's closed
+  diagnostics_scroll: Option<usize>,
+  // Furthest the diagnostics popup scrolls, set when it's drawn
+  diagnostics_max_scroll: Cell<usize>,
+  // Destructive action waiting for a yes/no answer
+  confirm: Option<DestructiveAction>,
+  // Transient message shown in a popup, e.g. the summary of a destructive action
+  message: Option<String>,
+  // Confirmation shown in the status bar for a moment, with the time it was given
+  notice: Option<(String, Instant)>,
+  // Opened on the first copy
+  clipboard: Option<Clipboard>,
+  show_help: bool,
+  // Digits typed so far to jump to an entry, with the time of the last keypress
+  pending_number: Option<(String, Instant)>,
+  // First press of a letter bound to `top` in a list, the second one jumps
+  pending_top: bool,
+  // Buffer for the bulk subscribe popup, `None` while it's closed
+  bulk_input: Option<String>,
+  // Query typed into the search line, `None` while it's closed
+  search_input: Option<String>,
+  // Text searched for in the open entry
+  entry_find: Option<EntryFind>,
+  // URLs of the bulk subscribe being fetched, in the order they were given
+  bulk_checking: Option<Vec<String>>,
+  // Per-URL outcome of the last bulk subscribe
+  bulk_results: Option<Vec<(String, Result<(), String>)>>,
+  add_feed: Option<AddFeedDialog>,
+  // When feeds were last fetched, for the status bar
+  last_fetched: Option<DateTime<Utc>>,
   exit: bool,
 }
 
+/// The system clipboard. On Linux, copied text only lasts as long as the handle that
+/// copied it, so it's kept open.
+struct Clipboard(arboard::Clipboard);
+
+impl std::fmt::Debug for Clipboard {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("Clipboard")
+  }
+}
+
+/// Inputs of the add feed popup
+#[derive(Debug, Default)]
+struct AddFeedDialog {
+  // URL, name and comma separated tags
+  fields: [String; 3],
+  focus: usize,
+  // Why the last attempt failed, shown until the next one
+  error: Option<String>,
+  // Set while the feed is fetched, only Esc is taken until it's done
+  checking: bool,
+}
+
+impl AddFeedDialog {
+  const LABELS: [&'static str; 3] = ["URL", "Name", "Tags"];
+}
+
+/// Text searched for in the open entry with `/`
+#[derive(Debug, Default)]
+struct EntryFind {
+  query: String,
+  case_sensitive: bool,
+  // Still being typed, keys go to the find line
+  editing: bool,
+  // Match scrolled to with n and N
+  current: Option<usize>,
+}
+
+// Entry key, terminal width, footnote links, wrapping, whether the text was loaded,
+// the focused link and the find with its current match
+type EntryViewKey = (
+  String,
+  String,
+  Option<String>,
+  u16,
+  bool,
+  bool,
+  bool,
+  Option<usize>,
+  Option<(String, bool, Option<usize>)>,
+);
+
+/// The open entry laid out for the width of the terminal
+#[derive(Debug)]
+struct EntryView {
+  key: EntryViewKey,
+  paragraph: Paragraph<'static>,
+  line_count: usize,
+  // URLs found in the body and the row of the laid out text each starts on
+  links: Vec<(String, usize)>,
+  // Row of the laid out text each match of the find is on
+  matches: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct LoadingState {
+  is_loading: bool,
+  // Name of the feed requested most recently
+  current: Option<String>,
+  started: Instant,
+  finished: Option<Instant>,
+  // Feeds in urls.toml, those done so far and how many of them failed
+  total: usize,
+  fetched: usize,
+  failed: usize,
+  // Title of the feed refreshed on its own, `None` when refreshing every feed
+  only: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum DestructiveAction {
+  MarkAllRead,
+  ClearCache,
+  /// Unsubscribe from the feed with this URL and title
+  DeleteFeed(String, String),
+}
+
+impl DestructiveAction {
+  fn prompt(&self) -> String {
+    match self {
+      DestructiveAction::MarkAllRead => "Mark all entries in every feed as read?".to_string(),
+      DestructiveAction::ClearCache => {
+        "Clear the cache, including all read and starred state?".to_string()
+      }
+      DestructiveAction::DeleteFeed(_, title) => {
+        format!("Unsubscribe from {} and delete its entries?", title)
+      }
+    }
+  }
+}
+
 #[derive(Debug)]
 enum ActiveList {
+  Tags,
   Feeds,
   Entries,
   Entry,
 }
 
+/// Feed and entry selected when the app was last quit
+#[derive(Debug)]
+struct LastSelection {
+  /// `selection_key` of the feed
+  feed: String,
+  /// Title and publication date of the entry, if one was selected
+  entry: Option<(String, Option<String>)>,
+}
+
+/// A row of the feeds list
+#[derive(Debug, PartialEq)]
+enum FeedRow {
+  /// Tag the feeds below are grouped under, skipped when moving the selection
+  Header(String),
+  Feed(usize),
+}
+
 impl App {
-  pub fn new(list: Vec<Feed>) -> Self {
+  pub fn new(
+    area_width: usize,
+    config: UserConfig,
+    key_map: KeyMap,
+    theme: Theme,
+    cache: FeedCache,
+    client: FeedClient,
+  ) -> Self {
+    let (updates_tx, updates) = mpsc::unbounded_channel();
+    let last_fetched = cache.last_refresh_time().unwrap_or_default();
+    let last_selection = load_last_selection(&cache).unwrap_or_default();
+    let fetched_at = cache.fetch_times().unwrap_or_default();
+    let feed_health = cache.feed_health().unwrap_or_default();
     App {
-      list,
+      // The built-in feed of every unread entry always comes first
+      list: vec![query::apply_query(
+        "All unread",
+        &Query {
+          filter: QueryFilter::Unread,
+          dedupe: false,
+          unread_only: false,
+        },
+        std::iter::empty(),
+      )],
       state: ListState::default().with_selected(Some(0)),
       entries_state: ListState::default(),
       index: 0,
@@ -60,273 +523,4043 @@ impl App {
       entry_open: false,
       scroll: 0,
       _scroll_state: ScrollbarState::new(0),
+      entry_height: Cell::new(0),
+      max_scroll: Cell::new(0),
+      furthest_scroll: Cell::new(0),
+      horizontal_scroll: 0,
+      max_horizontal_scroll: Cell::new(0),
+      focused_link: None,
+      entry_view: RefCell::new(None),
+      tags_list_area: Cell::default(),
+      feeds_list_area: Cell::default(),
+      entries_list_area: Cell::default(),
+      area_width,
+      split_view: config.split_view,
+      show_borders: config.show_borders,
+      size_bucket: None,
+      footnote_links: config.link_style == LinkStyle::Footnotes,
+      wrap_lines: true,
+      show_unread_only: false,
+      show_tags: false,
+      tag_filter: None,
+      read_in_view: Vec::new(),
+      marked_read: Vec::new(),
+      new_entries: HashSet::new(),
+      entry_selections: HashMap::new(),
+      last_selection,
+      entry_sort: config.entry_sort,
+      config,
+      key_map,
+      theme,
+      cache,
+      client,
+      updates_tx,
+      updates,
+      last_refresh: Instant::now(),
+      loading_state: LoadingState {
+        is_loading: false,
+        current: None,
+        started: Instant::now(),
+        finished: None,
+        total: 0,
+        fetched: 0,
+        failed: 0,
+        only: None,
+      },
+      feed_errors: Vec::new(),
+      fetch_errors: HashMap::new(),
+      fetched_at,
+      feed_health,
+      title_unread: None,
+      diagnostics_scroll: None,
+      diagnostics_max_scroll: Cell::new(0),
+      confirm: None,
+      message: None,
+      notice: None,
+      clipboard: None,
+      pending_number: None,
+      pending_top: false,
+      show_help: false,
+      bulk_input: None,
+      bulk_checking: None,
+      bulk_results: None,
+      add_feed: None,
+      last_fetched,
+      search_input: None,
+      entry_find: None,
       exit: false,
     }
   }
 
   pub fn run(&mut self, terminal: &mut ui::Tui) -> io::Result<()> {
     while !self.exit {
+      let mut updates = Vec::new();
+      while let Ok(update) = self.updates.try_recv() {
+        updates.push(update);
+      }
+      self.handle_feed_updates(updates);
+      let interval = Duration::from_secs(self.config.refresh_interval_minutes.saturating_mul(60));
+      if !interval.is_zero() && self.last_refresh.elapsed() >= interval {
+        self.refresh_feeds();
+      }
+      // Pick the layout remembered for this terminal size on startup and resize
+      let size_bucket = SizeBucket::from_width(terminal.size()?.width);
+      if self.size_bucket != Some(size_bucket) {
+        self.size_bucket = Some(size_bucket);
+        self.split_view = self.layout_for(size_bucket);
+      }
+      if self.config.terminal_title {
+        self.update_terminal_title(terminal)?;
+      }
       terminal.draw(|frame| self.render_frame(frame))?;
+      // The entry view shows a placeholder until the text is read from the cache
+      if self.load_entry_body() {
+        continue;
+      }
+      if self.config.mark_read_on_scroll_end {
+        self.mark_read_at_bottom();
+      }
       self.handle_events()?;
     }
+    // Losing the selection isn't worth failing to quit over
+    if self.entry_open {
+      self.save_scroll_position();
+    }
+    let _ = self.save_selection();
     Ok(())
   }
 
+  /// Puts the number of unread entries in front of the terminal title when it changed
+  fn update_terminal_title(&mut self, terminal: &mut ui::Tui) -> io::Result<()> {
+    let (_, unread) = self.subscription_counts();
+    if self.title_unread == Some(unread) {
+      return Ok(());
+    }
+    self.title_unread = Some(unread);
+    let title = match unread {
+      0 => "shinbun".to_string(),
+      unread => format!("({}) shinbun", unread),
+    };
+    execute!(terminal.backend_mut(), SetTitle(title))
+  }
+
+  /// Remembers the open feed and the selected entry for the next launch
+  fn save_selection(&self) -> rusqlite::Result<()> {
+    let feed = self.list.get(self.index).map(selection_key);
+    let entry = match self.active_list {
+      ActiveList::Tags | ActiveList::Feeds => None,
+      _ => self
+        .selected_entry_index()
+        .map(|i| &self.list[self.index].entries[i]),
+    };
+    self.cache.save_state("feed", feed.as_deref())?;
+    self
+      .cache
+      .save_state("entry_title", entry.map(|e| e.title.as_str()))?;
+    self.cache.save_state(
+      "entry_published",
+      entry.and_then(|e| e.published.as_deref()),
+    )
+  }
+
+  /// Selects the feed and entry the last session was left on once they're listed.
+  /// After the refresh is `complete`, a missing entry falls back to its feed and a
+  /// missing feed leaves the selection alone.
+  fn restore_selection(&mut self, complete: bool) {
+    let Some(last) = &self.last_selection else {
+      return;
+    };
+    let Some(index) = self.list.iter().position(|f| selection_key(f) == last.feed) else {
+      if complete {
+        self.last_selection = None;
+      }
+      return;
+    };
+    let row = last.entry.as_ref().and_then(|(title, published)| {
+      let feed = &self.list[index];
+      self.listed_entries(index).iter().position(|&i| {
+        let entry = &feed.entries[i];
+        entry.title == *title && entry.published == *published
+      })
+    });
+    if last.entry.is_some() && row.is_none() && !complete {
+      return;
+    }
+    self.last_selection = None;
+    if !self.listed_feeds().contains(&index) {
+      return;
+    }
+    self.index = index;
+    if let Some(row) = row {
+      self.active_list = ActiveList::Entries;
+      self.read_in_view.clear();
+      self.entries_state.select(Some(row));
+    }
+  }
+
   fn render_frame(&self, frame: &mut Frame) {
     frame.render_widget(self, frame.area());
   }
 
-  fn handle_events(&mut self) -> std::io::Result<()> {
-    match event::read()? {
-      // it's important to check that the event is a key press event as
-      // crossterm also emits key release and repeat events on Windows.
-      Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-        self.handle_key_event(key_event)
+  /// Lists the feeds of urls.toml as they were cached, to read while they're fetched
+  pub fn load_cached_feeds(&mut self, feeds: &[Feeds]) {
+    for (position, feed) in feeds.iter().enumerate() {
+      match self.cached_feed(feed, position) {
+        Ok(Some(cached)) => self.insert_feed(cached),
+        // Not fetched yet, it shows up once it is
+        Ok(None) => {}
+        Err(e) => {
+          self.message = Some(format!(
+            "Failed to load {} from the cache: {}",
+            feed.link, e
+          ))
+        }
       }
-      _ => {}
+    }
+    self.rebuild_query_feeds();
+    self.restore_selection(false);
+  }
+
+  /// The cached entries of a feed of urls.toml at `position`, with its settings from there
+  fn cached_feed(&self, feed: &Feeds, position: usize) -> rusqlite::Result<Option<Feed>> {
+    let Some(mut cached) = self.cache.load_feed(&feed.link)? else {
+      return Ok(None);
     };
-    Ok(())
+    cached.title = feed.name.clone().unwrap_or(cached.title);
+    cached.tags = feed.tags.clone();
+    cached.muted = feed.muted;
+    cached.display_max_age_days = feed.display_max_age_days;
+    cached.position = position;
+    Ok(Some(cached))
   }
 
-  fn handle_key_event(&mut self, key_event: KeyEvent) {
-    match key_event.code {
-      KeyCode::Char('q') | KeyCode::Char('Q') => self.exit(),
-      KeyCode::Up | KeyCode::Char('k') => self.previous(),
-      KeyCode::Down | KeyCode::Char('j') => self.next(),
-      KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.enter(),
-      KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => self.back(),
-      KeyCode::Char('s') => self.save_entry(),
-      KeyCode::Char('?') => self.help(),
-      _ => {}
+  /// Fetches every configured feed in the background, unless a refresh is already running
+  pub fn refresh_feeds(&mut self) {
+    match config::parse_feed_urls() {
+      Ok(feeds) => self.fetch_feeds(feeds),
+      Err(e) => self.message = Some(e),
     }
   }
 
-  fn exit(&mut self) {
-    self.exit = true;
+  /// Fetches `feeds`, which are the feeds of urls.toml in order, in the background
+  pub fn fetch_feeds(&mut self, feeds: Vec<Feeds>) {
+    if self.loading_state.is_loading {
+      return;
+    }
+    self.last_refresh = Instant::now();
+    self.feed_errors.clear();
+    self.start_fetch(feeds.into_iter().enumerate().collect(), None);
   }
 
-  fn previous(&mut self) {
-    if !self.entry_open {
-      match self.active_list {
-        ActiveList::Feeds => {
-          if self.index > 0 {
-            self.index -= 1;
-            self.state.select(Some(self.index));
-          }
-        }
-        ActiveList::Entries => {
-          if let Some(selected) = self.entries_state.selected() {
-            if selected > 0 {
-              self.entries_state.select(Some(selected - 1));
-            }
-          }
-        }
-        _ => {}
+  /// Fetches only the selected feed, or the feeds a query or search feed takes its
+  /// entries from
+  fn refresh_selected_feed(&mut self) {
+    if self.loading_state.is_loading {
+      return;
+    }
+    let Some(feed) = self.list.get(self.index) else {
+      return;
+    };
+    let sources: Vec<&str> = match &feed.kind {
+      FeedKind::Subscription => vec![feed.url.as_str()],
+      FeedKind::Query(query) => self
+        .list
+        .iter()
+        .filter(|f| f.kind == FeedKind::Subscription && query::feed_matches(&query.filter, f))
+        .map(|f| f.url.as_str())
+        .collect(),
+      FeedKind::Search(_) => feed.entries.iter().map(|e| e.feed_url.as_str()).collect(),
+    };
+    let feeds: Vec<(usize, Feeds)> = match config::parse_feed_urls() {
+      Ok(feeds) => feeds
+        .into_iter()
+        .enumerate()
+        .filter(|(_, f)| sources.contains(&f.link.as_str()))
+        .collect(),
+      Err(e) => {
+        self.message = Some(e);
+        return;
       }
-    } else {
-      self.scroll = self.scroll.saturating_sub(1);
-      //self.scroll_state = self.scroll_state.position(self.scroll)
+    };
+    if feeds.is_empty() {
+      return;
     }
+    let title = feed.title.clone();
+    // Errors of the other feeds still stand
+    self.feed_errors.retain(|(name, _)| {
+      !feeds
+        .iter()
+        .any(|(_, f)| f.name.as_ref().unwrap_or(&f.link) == name)
+    });
+    self.start_fetch(feeds, Some(title));
   }
 
-  fn next(&mut self) {
-    if !self.entry_open {
-      match self.active_list {
-        ActiveList::Feeds => {
-          if self.index + 1 < self.list.len() {
-            self.index += 1;
-            self.state.select(Some(self.index));
-          }
-        }
-        ActiveList::Entries => {
-          if let Some(selected) = self.entries_state.selected() {
-            let entries_len = self.list[self.index].entries.len();
-            if selected + 1 < entries_len {
-              self.entries_state.select(Some(selected + 1));
-            }
-          }
+  /// Fetches feeds with their positions in urls.toml in the background. `only` names
+  /// the feed being refreshed when it isn't every feed.
+  fn start_fetch(&mut self, feeds: Vec<(usize, Feeds)>, only: Option<String>) {
+    self.new_entries.clear();
+    self.loading_state = LoadingState {
+      is_loading: true,
+      current: None,
+      started: Instant::now(),
+      finished: None,
+      total: feeds.len(),
+      fetched: 0,
+      failed: 0,
+      only,
+    };
+    let validators = self.cache.load_validators().unwrap_or_default();
+    tokio::spawn(feeds::fetch_feed_with_progress(
+      self.client.clone(),
+      feeds,
+      validators,
+      self.area_width,
+      self.config.max_parsed_entries,
+      self.config.fetch_concurrency,
+      self.updates_tx.clone(),
+    ));
+  }
+
+  /// Handles the updates that arrived since the last frame, saving consecutive fetched
+  /// feeds together. Other updates may rename or load feeds in the cache, so they
+  /// wait for the fetched feeds before them to be saved.
+  fn handle_feed_updates(&mut self, updates: Vec<FeedUpdate>) {
+    let mut fetched = Vec::new();
+    for update in updates {
+      match update {
+        FeedUpdate::UpdateFeed { position, feed } => fetched.push(Feed { position, ..feed }),
+        update => {
+          self.save_fetched(std::mem::take(&mut fetched));
+          self.handle_feed_update(update);
         }
-        _ => {}
       }
-    } else {
-      //self.scroll = self.scroll.clamp(0, 150).into();
-      self.scroll = self.scroll.saturating_add(1);
-      //self.scroll_state = self.scroll_state.position(self.scroll)
     }
+    self.save_fetched(fetched);
   }
 
-  fn enter(&mut self) {
-    match self.active_list {
-      ActiveList::Feeds => {
-        self.active_list = ActiveList::Entries;
-        self.entries_state.select(Some(0));
+  /// Saves fetched feeds in one transaction and lists them with their read state
+  fn save_fetched(&mut self, mut feeds: Vec<Feed>) {
+    if feeds.is_empty() {
+      return;
+    }
+    self.loading_state.fetched += feeds.len();
+    for feed in &mut feeds {
+      // Listed feeds may have moved since the refresh started
+      if let Some(listed) = self.list.iter().find(|f| f.url == feed.url) {
+        feed.position = listed.position;
       }
-      ActiveList::Entries => {
-        self.active_list = ActiveList::Entry;
-        self.scroll = 0;
-        self.entry_open = true;
+    }
+    match self.cache.save_all_feeds(&feeds) {
+      Ok(inserted) => self.new_entries.extend(inserted.into_iter().map(entry_key)),
+      Err(e) => self.feed_errors.push(("Cache".to_string(), e.to_string())),
+    }
+    let urls: Vec<String> = feeds.iter().map(|f| f.url.clone()).collect();
+    for url in &urls {
+      self.feed_fetched(url);
+    }
+    for mut feed in feeds {
+      if let Err(e) = self.cache.load_read_state(&mut feed) {
+        self.feed_errors.push((feed.title.clone(), e.to_string()));
       }
-      _ => {}
+      self.insert_feed(feed);
     }
+    self.update_query_feeds(&urls);
+    self.clamp_feed_selection();
+    self.restore_selection(false);
   }
 
-  fn back(&mut self) {
-    match self.active_list {
-      ActiveList::Entry => {
-        self.active_list = ActiveList::Entries;
-        self.entry_open = false;
+  fn handle_feed_update(&mut self, update: FeedUpdate) {
+    match update {
+      FeedUpdate::FetchingFeed(name) => self.loading_state.current = Some(name),
+      FeedUpdate::UpdateFeed { position, feed } => {
+        self.save_fetched(vec![Feed { position, ..feed }])
+      }
+      FeedUpdate::NotModified { position, feed } => match self.cached_feed(&feed, position) {
+        Ok(Some(cached)) => {
+          self.loading_state.fetched += 1;
+          let urls = [cached.url.clone()];
+          if let Err(e) = self.cache.record_not_modified(&cached.url) {
+            self.feed_errors.push(("Cache".to_string(), e.to_string()));
+          }
+          self.feed_fetched(&cached.url);
+          self.insert_feed(cached);
+          self.update_query_feeds(&urls);
+          self.clamp_feed_selection();
+          self.restore_selection(false);
+        }
+        Ok(None) => {
+          let error = "Not modified, but missing from the cache".to_string();
+          self.feed_failed(feed.link.clone(), feed.link, error);
+        }
+        Err(e) => self.feed_failed(feed.link.clone(), feed.link, e.to_string()),
+      },
+      FeedUpdate::FeedMoved { position, from, to } => {
+        self.fetch_errors.remove(&from);
+        match record_feed_move(&self.cache, &from, &to) {
+          // The feed's update now replaces the one already subscribed
+          Ok(true) => {
+            if let Err(e) = self.forget_feed(&from, position) {
+              self.feed_errors.push((from, e.to_string()));
+            }
+          }
+          Ok(false) => {
+            if let Some(feed) = self.list.iter_mut().find(|f| f.url == from) {
+              feed.url = to.clone();
+              for entry in &mut feed.entries {
+                entry.feed_url = to.clone();
+              }
+            }
+            self.rebuild_query_feeds();
+          }
+          Err(e) => self.feed_errors.push((from, e)),
+        }
+      }
+      FeedUpdate::FeedError { url, name, error } => self.feed_failed(url, name, error),
+      FeedUpdate::Validated { feed, result } => self.feed_validated(feed, result),
+      FeedUpdate::BulkValidated(validated) => self.feeds_validated(validated),
+      FeedUpdate::FetchComplete => {
+        self.loading_state.is_loading = false;
+        self.loading_state.finished = Some(Instant::now());
+        self.restore_selection(true);
+        if let Ok(health) = self.cache.feed_health() {
+          self.feed_health = health;
+        }
+        // Feeds that weren't modified keep their fetch time in the cache, but were checked
+        if self.loading_state.only.is_none() {
+          self.last_fetched = Some(Utc::now());
+        }
+        let (retention_days, max_entries) =
+          (self.config.retention_days, self.config.max_entries_per_feed);
+        if retention_days.is_some() || max_entries.is_some() {
+          if let Err(e) = self.cache.prune_entries(retention_days, max_entries) {
+            self.feed_errors.push(("Cache".to_string(), e.to_string()));
+          }
+        }
       }
-      ActiveList::Entries => self.active_list = ActiveList::Feeds,
-      _ => {}
     }
   }
 
-  fn help(&mut self) {
-    todo!()
+  fn feed_failed(&mut self, url: String, name: String, error: String) {
+    self.loading_state.fetched += 1;
+    self.loading_state.failed += 1;
+    if let Err(e) = self.cache.record_failure(&url) {
+      self.feed_errors.push(("Cache".to_string(), e.to_string()));
+    }
+    self.fetch_errors.insert(url, error.clone());
+    self.feed_errors.push((name, error));
   }
 
-  fn save_entry(&mut self) {
-    todo!()
+  /// Clears the error of a feed that was just fetched or found unchanged
+  fn feed_fetched(&mut self, url: &str) {
+    self.fetch_errors.remove(url);
+    self.fetched_at.insert(url.to_string(), Utc::now());
   }
-}
-
-impl Widget for &App {
-  fn render(self, area: Rect, buf: &mut Buffer) {
-    let title = Title::from(" Shinbun ".bold().yellow());
-    let instructions = Title::from(Line::from(vec![" Quit ".into(), "<q> ".bold()]));
-    let block = Block::default()
-      .title(title.alignment(Alignment::Left))
-      .title(
-        instructions
-          .alignment(Alignment::Left)
-          .position(block::Position::Bottom),
-      )
-      .title_bottom(Line::from(" Help <?> ".blue()).right_aligned())
-      .borders(Borders::ALL)
-      .border_style(Style::new().blue())
-      .border_set(border::PLAIN);
 
-    let inner_area = block.inner(area);
-    block.render(area, buf);
-    if self.entry_open {
-      // Render the pane
-      if let Some(feed) = self.list.get(self.index) {
-        if let Some(selected_entry) = self.entries_state.selected() {
-          if let Some(entry) = feed.entries.get(selected_entry) {
-            let mut entry_content = vec![
-              Line::from(format!("Title: {}", entry.title).magenta()), // Entry title
-              Line::from(format!("Feed: {}", feed.title).cyan()),      // Feed title
-              Line::from(
-                format!(
-                  "Published: {}",
-                  entry.published.as_deref().unwrap_or("Unknown")
-                )
-                .yellow(),
-              ), // Publication date
-            ];
-
-            if !entry.links.is_empty() {
-              entry_content.push(Line::from(
-                format!("Link: {}", entry.links.join(", ")).blue(),
-              ));
-            }
-
-            if !entry.media.is_empty() {
-              entry_content.push(Line::from(format!("Media: {}", entry.media).blue()));
-            }
-
-            entry_content.push(Line::from("")); // Add a blank line for separation
+  /// Adds a feed to the list, keeping it sorted as in urls.toml since feeds land in any order.
+  /// A feed that's already listed gets replaced by its refreshed version.
+  fn insert_feed(&mut self, feed: Feed) {
+    if let Some(existing) = self.list.iter().position(|f| f.url == feed.url) {
+      // The feed may have been moved since the refresh started
+      let position = self.list[existing].position;
+      let feed = Feed { position, ..feed };
+      if existing == self.index {
+        self.keep_open_entries(|app| app.list[existing] = feed);
+      } else {
+        self.list[existing] = feed;
+      }
+      return;
+    }
+    // Subscriptions follow the unread feed at the top, query feeds and search results
+    // come after them
+    let insert_at = 1
+      + self.list[1..]
+        .partition_point(|f| f.kind == FeedKind::Subscription && f.position < feed.position);
+    // Keep the opened feed selected when one lands above it
+    let browsing_feeds = matches!(self.active_list, ActiveList::Tags | ActiveList::Feeds);
+    if insert_at <= self.index && !self.list.is_empty() && !browsing_feeds {
+      self.index += 1;
+    }
+    self.list.insert(insert_at, feed);
+  }
 
-            // Append the plain text content
-            let plain_text_lines: Vec<Line> = entry.plain_text.lines().map(Line::from).collect();
+  /// Adds the query feeds of urls.toml after the subscriptions, reporting invalid queries
+  pub fn load_query_feeds(&mut self, queries: &[QueryFeed]) {
+    let mut errors = Vec::new();
+    for query_feed in queries {
+      match query::parse_query(&query_feed.query) {
+        Ok(filter) => {
+          let query = Query {
+            filter,
+            dedupe: query_feed.dedupe,
+            unread_only: query_feed.unread_only,
+          };
+          let feed = query::apply_query(&query_feed.name, &query, self.list.iter());
+          self.list.push(feed);
+        }
+        Err(e) => errors.push(format!("Invalid query for {}: {}", query_feed.name, e)),
+      }
+    }
+    if !errors.is_empty() {
+      self.message = Some(errors.join("\n"));
+    }
+  }
 
-            // Combine metadata and text content
-            entry_content.extend(plain_text_lines);
-            // Rest of the rendering logic
-            let paragraph = Paragraph::new(entry_content)
-              .block(
-                Block::default()
-                  .padding(Padding::new(area.width / 20, area.width / 20, 1, 1))
-                  .borders(Borders::NONE),
-              )
-              .scroll((self.scroll as u16, 0))
-              .wrap(Wrap { trim: false });
+  /// Updates the query feeds with the entries of the feeds with these URLs, which
+  /// are cheaper to merge in after a fetch than rebuilding every query feed
+  fn update_query_feeds(&mut self, urls: &[String]) {
+    self.keep_open_entries(|app| {
+      for i in 0..app.list.len() {
+        let (before, rest) = app.list.split_at_mut(i);
+        let Some((query_feed, after)) = rest.split_first_mut() else {
+          break;
+        };
+        query::update_query(query_feed, urls, before.iter().chain(after.iter()));
+      }
+    });
+  }
 
-            paragraph.render(inner_area, buf);
-          }
+  /// Recomputes every query feed from the current subscriptions
+  fn rebuild_query_feeds(&mut self) {
+    self.keep_open_entries(|app| {
+      for i in 0..app.list.len() {
+        if let FeedKind::Query(query) = &app.list[i].kind {
+          let feed = query::apply_query(&app.list[i].title, query, app.list.iter());
+          app.list[i] = feed;
         }
       }
-    } else {
-      // Render the lists
-      let horizontal_split = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(inner_area);
+    });
+  }
 
-      let feeds = self
-        .list
+  /// Runs `replace`, which changes the entries of the open feed, keeping the selected
+  /// entry and those read since the feed was opened. They're found again by their
+  /// `entry_key`, as the new entries may be in another order or have others among them.
+  fn keep_open_entries(&mut self, replace: impl FnOnce(&mut Self)) {
+    let keys = |app: &Self| {
+      let feed = app.list.get(app.index)?;
+      let selected = app
+        .selected_entry_index()
+        .map(|i| entry_key(&feed.entries[i]));
+      let read: Vec<_> = app
+        .read_in_view
+        .iter()
+        .filter_map(|&i| feed.entries.get(i))
+        .map(entry_key)
+        .collect();
+      Some((selected, read))
+    };
+    let Some((selected, read)) = keys(self) else {
+      return replace(self);
+    };
+    replace(self);
+    let Some(feed) = self.list.get(self.index) else {
+      return;
+    };
+    // Before the entries are listed, read ones only stay listed through it
+    self.read_in_view = (0..feed.entries.len())
+      .filter(|&i| read.contains(&entry_key(&feed.entries[i])))
+      .collect();
+    if let Some(selected) = selected {
+      let row = self
+        .listed_entries(self.index)
         .iter()
-        .map(|l| format!(" {}", &l.title,))
-        .collect::<List>();
+        .position(|&i| entry_key(&feed.entries[i]) == selected);
+      if row.is_some() {
+        self.entries_state.select(row);
+      }
+    }
+    // The entry may be gone, and the feed have shrunk with it
+    self.clamp_entry_selection();
+  }
 
-      let left_block = Block::default()
-        .title(" Feeds ".green())
-        .title(format!(" {} ", self.list.iter().count().to_string()).yellow())
-        .borders(Borders::ALL)
-        .border_style(Style::new().blue())
-        .border_set(border::PLAIN);
+  /// Indices of the feeds shown in the feeds list, in the order they're listed.
+  /// The open feed stays listed while it's being read, even when it no longer has
+  /// unread entries.
+  fn listed_feeds(&self) -> Vec<usize> {
+    feed_indices(self.listed_feed_rows())
+  }
 
-      let feeds_highlight_style = match self.active_list {
-        ActiveList::Feeds => Style::default().bg(Color::Yellow).fg(Color::Black),
-        ActiveList::Entries => Style::default().yellow(),
-        _ => Style::default(),
+  /// Rows of the feeds list, with tag headers when feeds are grouped
+  fn listed_feed_rows(&self) -> Vec<FeedRow> {
+    let browsing_feeds = matches!(self.active_list, ActiveList::Tags | ActiveList::Feeds);
+    let listed = (0..self.list.len()).filter(|&i| {
+      let feed = &self.list[i];
+      let reading = i == self.index && !browsing_feeds;
+      let tagged = match &self.tag_filter {
+        Some(tag) => feed.tags.as_ref().is_some_and(|tags| tags.contains(tag)),
+        None => true,
       };
+      reading || (tagged && (!self.show_unread_only || has_unread(feed)))
+    });
+    self.feed_rows(listed)
+  }
 
-      StatefulWidget::render(
-        feeds
-          .block(left_block)
-          .highlight_style(feeds_highlight_style),
-        horizontal_split[0],
-        buf,
-        &mut self.state.to_owned(),
-      );
-
-      let selected_index = self.state.selected().unwrap_or(0);
-      let entries = if let Some(feed) = self.list.get(selected_index) {
-        feed
-          .entries
-          .iter()
-          .map(|e| ListItem::new(format!(" {}", e.title)))
-          .collect::<Vec<_>>()
+  /// Tags of the subscriptions in the order they first appear, with the unread
+  /// entries of the feeds that have each one
+  fn tags(&self) -> Vec<(&str, usize)> {
+    let mut tags: Vec<(&str, usize)> = Vec::new();
+    let subscriptions = self
+      .list
+      .iter()
+      .filter(|f| f.kind == FeedKind::Subscription);
+    for feed in subscriptions {
+      let unread = if feed.muted {
+        0
       } else {
-        vec![]
+        visible_entries(feed)
+          .iter()
+          .filter(|&&i| !feed.entries[i].read)
+          .count()
       };
+      for tag in feed.tags.iter().flatten() {
+        match tags.iter_mut().find(|(name, _)| name == tag) {
+          Some((_, count)) => *count += unread,
+          None => tags.push((tag, unread)),
+        }
+      }
+    }
+    tags
+  }
 
-      let right_block = Block::default()
-        .title(" Entries ".green())
-        .title(format!(" {} ", entries.iter().count()).yellow())
-        .borders(Borders::ALL)
-        .border_style(Style::new().blue())
-        .border_set(border::PLAIN);
+  /// Shows the tag sidebar and moves there, or hides it with the filter when it's
+  /// already in focus
+  fn toggle_tags(&mut self) {
+    if self.entry_open {
+      return;
+    }
+    if self.show_tags && matches!(self.active_list, ActiveList::Tags) {
+      self.show_tags = false;
+      self.tag_filter = None;
+      self.active_list = ActiveList::Feeds;
+      self.clamp_feed_selection();
+      return;
+    }
+    if matches!(self.active_list, ActiveList::Entries) {
+      self.back();
+    }
+    self.show_tags = true;
+    self.active_list = ActiveList::Tags;
+  }
 
-      let secondary_list = List::new(entries)
-        .block(right_block.clone())
-        .highlight_style(Style::default().yellow().bold());
+  /// Filters the feeds by the tag in row `row` of the sidebar, the first row lists
+  /// every feed
+  fn select_tag_row(&mut self, row: usize) {
+    self.tag_filter = match row {
+      0 => None,
+      row => match self.tags().get(row - 1) {
+        Some((tag, _)) => Some(tag.to_string()),
+        None => return,
+      },
+    };
+    self.clamp_feed_selection();
+  }
 
-      let entries_highlight_style = match self.active_list {
-        ActiveList::Entries => Style::default().bg(Color::Yellow).fg(Color::Black).bold(),
-        ActiveList::Feeds => Style::default(),
-        _ => Style::default(),
-      };
+  /// Row of the tag sidebar selected by the filter
+  fn selected_tag_row(&self) -> usize {
+    let Some(filter) = &self.tag_filter else {
+      return 0;
+    };
+    self
+      .tags()
+      .iter()
+      .position(|(tag, _)| tag == filter)
+      .map_or(0, |i| i + 1)
+  }
 
-      StatefulWidget::render(
-        secondary_list
-          .block(right_block)
-          .highlight_style(entries_highlight_style),
-        horizontal_split[1],
-        buf,
-        &mut self.entries_state.to_owned(),
-      );
+  /// Orders feeds for the feeds list. Grouped feeds come under their first tag, with
+  /// the tags in the order they first appear and untagged feeds last.
+  fn feed_rows(&self, feeds: impl Iterator<Item = usize>) -> Vec<FeedRow> {
+    if !self.config.group_by_tag {
+      return feeds.map(FeedRow::Feed).collect();
     }
+    let mut rows = Vec::new();
+    let mut groups: Vec<(&str, Vec<usize>)> = Vec::new();
+    let mut untagged = Vec::new();
+    for i in feeds {
+      let feed = &self.list[i];
+      if feed.kind != FeedKind::Subscription {
+        rows.push(FeedRow::Feed(i));
+        continue;
+      }
+      match group_tag(feed) {
+        Some(tag) => match groups.iter_mut().find(|(name, _)| *name == tag) {
+          Some((_, group)) => group.push(i),
+          None => groups.push((tag, vec![i])),
+        },
+        None => untagged.push(i),
+      }
+    }
+    if !untagged.is_empty() {
+      groups.push(("Untagged", untagged));
+    }
+    for (tag, group) in groups {
+      rows.push(FeedRow::Header(tag.to_string()));
+      rows.extend(group.into_iter().map(FeedRow::Feed));
+    }
+    rows
+  }
+
+  /// Indices into the entries of a feed of the rows shown in the entries list
+  fn listed_entries(&self, feed_index: usize) -> Vec<usize> {
+    let Some(feed) = self.list.get(feed_index) else {
+      return Vec::new();
+    };
+    // Entries read since the feed was opened keep their place
+    let read = |i: usize| {
+      feed.entries[i].read && !(feed_index == self.index && self.read_in_view.contains(&i))
+    };
+    // Query feeds of unread entries drop the ones read since they were built
+    let unread_only =
+      self.show_unread_only || matches!(&feed.kind, FeedKind::Query(query) if query.unread_only);
+    let mut listed: Vec<usize> = visible_entries(feed)
+      .into_iter()
+      .filter(|&i| !unread_only || !read(i))
+      .collect();
+    let published = |i: usize| query::published(&feed.entries[i]);
+    match self.entry_sort {
+      EntrySort::DateDesc => listed.sort_by_cached_key(|&i| Reverse(published(i))),
+      EntrySort::DateAsc => listed.sort_by_cached_key(|&i| {
+        let published = published(i);
+        (published.is_none(), published)
+      }),
+      EntrySort::UnreadFirst => listed.sort_by_cached_key(|&i| (read(i), Reverse(published(i)))),
+      EntrySort::Title => listed.sort_by_cached_key(|&i| feed.entries[i].title.to_lowercase()),
+    }
+    listed
+  }
+
+  /// Moves the feed selection to a listed feed, preferring the next one down
+  fn clamp_feed_selection(&mut self) {
+    let listed = self.listed_feeds();
+    if !listed.contains(&self.index) {
+      let order = feed_indices(self.feed_rows(0..self.list.len()));
+      let mut below = order.into_iter().skip_while(|&i| i != self.index);
+      let next = below.find(|i| listed.contains(i));
+      if let Some(index) = next.or(listed.last().copied()) {
+        self.index = index;
+      }
+    }
+  }
+
+  /// Keeps the entries selection within the listed entries
+  fn clamp_entry_selection(&mut self) {
+    let entries_len = self.listed_entries(self.index).len();
+    if let Some(selected) = self.entries_state.selected() {
+      if selected >= entries_len {
+        self.entries_state.select(entries_len.checked_sub(1));
+      }
+    }
+  }
+
+  fn toggle_unread_only(&mut self) {
+    let selected_entry = self.selected_entry_index();
+    self.show_unread_only = !self.show_unread_only;
+    self.clamp_feed_selection();
+    // Stay on the same entry if it's still listed
+    if let Some(selected) = selected_entry {
+      if let Some(position) = self
+        .listed_entries(self.index)
+        .iter()
+        .position(|&i| i == selected)
+      {
+        self.entries_state.select(Some(position));
+      }
+    }
+    self.clamp_entry_selection();
+  }
+
+  /// Switches to the next entry order, staying on the selected entry
+  fn cycle_entry_sort(&mut self) {
+    let selected_entry = self.selected_entry_index();
+    self.entry_sort = self.entry_sort.next();
+    // Rows remembered for other feeds point at different entries now
+    self.entry_selections.clear();
+    if let Some(selected) = selected_entry {
+      let position = self
+        .listed_entries(self.index)
+        .iter()
+        .position(|&i| i == selected);
+      self.entries_state.select(position);
+    }
+  }
+
+  fn handle_events(&mut self) -> std::io::Result<()> {
+    // Updates from the background are picked up between events
+    let checking =
+      self.add_feed.as_ref().is_some_and(|d| d.checking) || self.bulk_checking.is_some();
+    let tick_rate = if self.loading_state.is_loading || checking {
+      LOADING_TICK_RATE
+    } else {
+      TICK_RATE
+    };
+    // Return on every tick so the next frame gets redrawn
+    if !event::poll(tick_rate)? {
+      if let Some((_, typed_at)) = &self.pending_number {
+        if typed_at.elapsed() >= NUMBER_TIMEOUT {
+          self.jump_to_pending_number();
+        }
+      }
+      return Ok(());
+    }
+    let event = event::read()?;
+    let Event::Resize(mut width, mut height) = event else {
+      self.handle_event(event);
+      return Ok(());
+    };
+    // Dragging the window edge sends a burst of resizes, only the last size is laid out
+    let mut next = None;
+    while next.is_none() && event::poll(Duration::ZERO)? {
+      match event::read()? {
+        Event::Resize(w, h) => (width, height) = (w, h),
+        event => next = Some(event),
+      }
+    }
+    self.resize(width, height);
+    if let Some(event) = next {
+      self.handle_event(event);
+    }
+    Ok(())
+  }
+
+  fn handle_event(&mut self, event: Event) {
+    match event {
+      // it's important to check that the event is a key press event as
+      // crossterm also emits key release and repeat events on Windows.
+      Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+        self.handle_key_event(key_event)
+      }
+      Event::Mouse(mouse_event) => self.handle_mouse_event(mouse_event),
+      Event::Paste(text) => {
+        if let Some(input) = self.bulk_input.as_mut() {
+          input.push_str(&text);
+        } else if let Some(dialog) = self.add_feed.as_mut() {
+          dialog.fields[dialog.focus].push_str(text.trim());
+        }
+      }
+      _ => {}
+    }
+  }
+
+  fn handle_mouse_event(&mut self, mouse_event: MouseEvent) {
+    if matches!(mouse_event.kind, MouseEventKind::Moved) {
+      return;
+    }
+    self.last_selection = None;
+    // Popups only take keys
+    let popup_open = self.show_help
+      || self.diagnostics_scroll.is_some()
+      || self.message.is_some()
+      || self.confirm.is_some()
+      || self.bulk_checking.is_some()
+      || self.bulk_results.is_some()
+      || self.bulk_input.is_some()
+      || self.add_feed.is_some()
+      || self.search_input.is_some()
+      || self.entry_find.as_ref().is_some_and(|f| f.editing);
+    if popup_open {
+      return;
+    }
+    match mouse_event.kind {
+      MouseEventKind::ScrollDown if self.entry_open => {
+        self.scroll_to(self.current_scroll().saturating_add(MOUSE_SCROLL_LINES))
+      }
+      MouseEventKind::ScrollUp if self.entry_open => {
+        self.scroll_to(self.current_scroll().saturating_sub(MOUSE_SCROLL_LINES))
+      }
+      MouseEventKind::ScrollDown => self.next(),
+      MouseEventKind::ScrollUp => self.previous(),
+      MouseEventKind::Down(MouseButton::Left) => {
+        self.click(layout::Position::new(mouse_event.column, mouse_event.row))
+      }
+      _ => {}
+    }
+  }
+
+  /// Selects the clicked feed or entry, a click on the selected one opens it
+  fn click(&mut self, position: layout::Position) {
+    if self.entry_open {
+      return;
+    }
+    if let Some(row) = clicked_row(self.tags_list_area.get(), position) {
+      if row <= self.tags().len() {
+        if matches!(self.active_list, ActiveList::Entries) {
+          self.back();
+        }
+        self.active_list = ActiveList::Tags;
+        self.select_tag_row(row);
+      }
+    } else if let Some(row) = clicked_row(self.feeds_list_area.get(), position) {
+      let Some(&FeedRow::Feed(index)) = self.listed_feed_rows().get(row) else {
+        return;
+      };
+      match self.active_list {
+        ActiveList::Feeds if index == self.index => self.enter(),
+        ActiveList::Entries if index == self.index => self.back(),
+        _ => {
+          if matches!(self.active_list, ActiveList::Entries) {
+            self.back();
+          }
+          self.active_list = ActiveList::Feeds;
+          self.index = index;
+        }
+      }
+    } else if let Some(row) = clicked_row(self.entries_list_area.get(), position) {
+      if row >= self.listed_entries(self.index).len() {
+        return;
+      }
+      if matches!(self.active_list, ActiveList::Entries)
+        && self.entries_state.selected() == Some(row)
+      {
+        self.open_entry();
+        return;
+      }
+      if matches!(self.active_list, ActiveList::Tags | ActiveList::Feeds) {
+        self.active_list = ActiveList::Entries;
+        self.read_in_view.clear();
+      }
+      self.entries_state.select(Some(row));
+    }
+  }
+
+  fn handle_key_event(&mut self, key_event: KeyEvent) {
+    // Whatever is selected once the user starts moving around stays selected
+    self.last_selection = None;
+    if self.show_help {
+      // Swallow everything else while the help is open
+      if key_event.code == KeyCode::Esc || self.key_map.action(&key_event) == Some(Action::Help) {
+        self.show_help = false;
+      }
+      return;
+    }
+    if let Some(scroll) = self.diagnostics_scroll {
+      let max_scroll = self.diagnostics_max_scroll.get();
+      self.diagnostics_scroll = match self.key_map.action(&key_event) {
+        Some(Action::Next) => Some((scroll + 1).min(max_scroll)),
+        Some(Action::Prev) => Some(scroll.saturating_sub(1)),
+        Some(Action::Top) => Some(0),
+        Some(Action::Bottom) => Some(max_scroll),
+        Some(Action::Diagnostics) => None,
+        _ if key_event.code == KeyCode::Esc => None,
+        _ => Some(scroll),
+      };
+      return;
+    }
+    if self.message.is_some() {
+      // Any key dismisses the message popup
+      self.message = None;
+      return;
+    }
+    if let Some(action) = &self.confirm {
+      match key_event.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+          let action = action.clone();
+          self.confirm = None;
+          self.perform(action);
+        }
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => self.confirm = None,
+        _ => {}
+      }
+      return;
+    }
+    if self.bulk_results.is_some() {
+      // Any key dismisses the results popup
+      self.bulk_results = None;
+      return;
+    }
+    if self.bulk_checking.is_some() {
+      if key_event.code == KeyCode::Esc {
+        self.bulk_checking = None;
+      }
+      return;
+    }
+    if self.bulk_input.is_some() {
+      self.handle_bulk_input(key_event);
+      return;
+    }
+    if self.add_feed.is_some() {
+      self.handle_add_feed_input(key_event);
+      return;
+    }
+    if self.search_input.is_some() {
+      self.handle_search_input(key_event);
+      return;
+    }
+    if let Some(find) = &self.entry_find {
+      if find.editing {
+        self.handle_entry_find_input(key_event);
+        return;
+      }
+      if key_event.code == KeyCode::Esc {
+        self.entry_find = None;
+        return;
+      }
+    }
+    if let ActiveList::Entries = self.active_list {
+      match key_event.code {
+        KeyCode::Char(c) if c.is_ascii_digit() => {
+          let (digits, typed_at) = self
+            .pending_number
+            .get_or_insert_with(|| (String::new(), Instant::now()));
+          digits.push(c);
+          *typed_at = Instant::now();
+          return;
+        }
+        KeyCode::Enter if self.pending_number.is_some() => {
+          self.jump_to_pending_number();
+          return;
+        }
+        // Any other key abandons the typed number
+        _ => self.pending_number = None,
+      }
+    }
+    // Unwrapped lines scroll sideways instead of leaving the entry
+    if self.entry_open && !self.wrap_lines && key_event.modifiers.is_empty() {
+      let max = self.max_horizontal_scroll.get();
+      match key_event.code {
+        KeyCode::Left => {
+          self.horizontal_scroll = self
+            .horizontal_scroll
+            .min(max)
+            .saturating_sub(HORIZONTAL_SCROLL_COLUMNS);
+          return;
+        }
+        KeyCode::Right => {
+          self.horizontal_scroll = (self.horizontal_scroll + HORIZONTAL_SCROLL_COLUMNS).min(max);
+          return;
+        }
+        _ => {}
+      }
+    }
+    let action = self.key_map.action(&key_event);
+    // In the lists a letter bound to `top` is pressed twice, like gg in vi
+    let pending_top = std::mem::take(&mut self.pending_top);
+    if action == Some(Action::Top)
+      && !self.entry_open
+      && matches!(key_event.code, KeyCode::Char(_))
+      && !pending_top
+    {
+      self.pending_top = true;
+      return;
+    }
+    let Some(action) = action else {
+      return;
+    };
+    match action {
+      Action::Quit => self.exit(),
+      Action::Prev => self.previous(),
+      Action::Next => self.next(),
+      Action::Enter => self.enter(),
+      Action::Back => self.back(),
+      Action::MoveUp => self.move_feed(false),
+      Action::MoveDown => self.move_feed(true),
+      Action::PageDown => self.scroll_page(true),
+      Action::PageUp => self.scroll_page(false),
+      Action::Top => self.jump(false),
+      Action::Bottom => self.jump(true),
+      Action::NextUnread if self.entry_open && self.entry_find.is_some() => self.find_next(true),
+      Action::PrevUnread if self.entry_open && self.entry_find.is_some() => self.find_next(false),
+      Action::NextUnread => self.jump_to_unread(true),
+      Action::PrevUnread => self.jump_to_unread(false),
+      Action::Save => self.save_entry(),
+      Action::Star => self.toggle_starred(),
+      Action::Play => self.play_enclosure(),
+      Action::CopyLink => self.copy_link(),
+      Action::NextLink => self.focus_link(true),
+      Action::PrevLink => self.focus_link(false),
+      Action::Refresh => self.refresh_feeds(),
+      Action::RefreshFeed => self.refresh_selected_feed(),
+      Action::Subscribe => self.bulk_input = Some(String::new()),
+      Action::AddFeed => self.add_feed = Some(AddFeedDialog::default()),
+      Action::Search if !self.entry_open => self.search_input = Some(String::new()),
+      Action::Search => {
+        let case_sensitive = self.entry_find.as_ref().is_some_and(|f| f.case_sensitive);
+        self.entry_find = Some(EntryFind {
+          case_sensitive,
+          editing: true,
+          ..Default::default()
+        });
+      }
+      Action::Tags => self.toggle_tags(),
+      Action::Mute => self.toggle_mute(),
+      Action::MarkFeedRead => self.mark_feed_read(),
+      Action::UnreadOnly => self.toggle_unread_only(),
+      Action::SortEntries => self.cycle_entry_sort(),
+      Action::LinkStyle => {
+        self.footnote_links = !self.footnote_links;
+        self.focused_link = None;
+      }
+      Action::Wrap => {
+        self.wrap_lines = !self.wrap_lines;
+        self.horizontal_scroll = 0;
+      }
+      Action::SplitView => self.toggle_split_view(),
+      Action::Borders => self.show_borders = !self.show_borders,
+      Action::MarkAllRead => self.request(DestructiveAction::MarkAllRead),
+      Action::UndoMarkRead => self.undo_mark_read(),
+      Action::DeleteFeed => self.request_delete_feed(),
+      Action::ClearCache => self.request(DestructiveAction::ClearCache),
+      Action::Diagnostics => self.show_diagnostics(),
+      Action::Help => self.help(),
+    }
+  }
+
+  fn exit(&mut self) {
+    self.exit = true;
+  }
+
+  fn previous(&mut self) {
+    if !self.entry_open {
+      match self.active_list {
+        ActiveList::Tags => {
+          let rows = self.tags().len() + 1;
+          match self.selected_tag_row() {
+            0 if self.config.wrap_navigation => self.select_tag_row(rows - 1),
+            0 => {}
+            row => self.select_tag_row(row - 1),
+          }
+        }
+        ActiveList::Feeds => {
+          let listed = self.listed_feeds();
+          let position = listed.iter().position(|&i| i == self.index);
+          let previous = position.and_then(|p| p.checked_sub(1)).map(|p| &listed[p]);
+          let wrapped = listed.last().filter(|_| self.config.wrap_navigation);
+          if let Some(&index) = previous.or(wrapped) {
+            self.index = index;
+          }
+        }
+        ActiveList::Entries => {
+          if let Some(selected) = self.entries_state.selected() {
+            let entries_len = self.listed_entries(self.index).len();
+            if selected > 0 {
+              self.entries_state.select(Some(selected - 1));
+            } else if self.config.wrap_navigation && entries_len > 0 {
+              self.entries_state.select(Some(entries_len - 1));
+            }
+          }
+        }
+        _ => {}
+      }
+    } else {
+      self.scroll_to(self.current_scroll().saturating_sub(1));
+      //self.scroll_state = self.scroll_state.position(self.scroll)
+    }
+  }
+
+  fn next(&mut self) {
+    if !self.entry_open {
+      match self.active_list {
+        ActiveList::Tags => {
+          let row = self.selected_tag_row() + 1;
+          if row <= self.tags().len() {
+            self.select_tag_row(row);
+          } else if self.config.wrap_navigation {
+            self.select_tag_row(0);
+          }
+        }
+        ActiveList::Feeds => {
+          let listed = self.listed_feeds();
+          let position = listed.iter().position(|&i| i == self.index);
+          let next = position.and_then(|p| listed.get(p + 1));
+          let wrapped = listed.first().filter(|_| self.config.wrap_navigation);
+          if let Some(&index) = next.or(wrapped) {
+            self.index = index;
+          }
+        }
+        ActiveList::Entries => {
+          if let Some(selected) = self.entries_state.selected() {
+            let entries_len = self.listed_entries(self.index).len();
+            if selected + 1 < entries_len {
+              self.entries_state.select(Some(selected + 1));
+            } else if self.config.wrap_navigation {
+              self.entries_state.select(Some(0));
+            }
+          }
+        }
+        _ => {}
+      }
+    } else {
+      //self.scroll = self.scroll.clamp(0, 150).into();
+      self.scroll_to(self.current_scroll().saturating_add(1));
+      //self.scroll_state = self.scroll_state.position(self.scroll)
+    }
+  }
+
+  /// Scrolls the open entry to `scroll`, stopping where its last line reaches the bottom
+  fn scroll_to(&mut self, scroll: usize) {
+    if self.entry_open {
+      self.scroll = scroll.min(self.max_scroll.get());
+    }
+  }
+
+  /// Lays out the open entry for a frame of `area`, reusing the last layout while the
+  /// entry, width and link style stay the same
+  fn layout_entry(&self, area: Rect) -> Option<RefMut<'_, EntryView>> {
+    let entry = self
+      .list
+      .get(self.index)?
+      .entries
+      .get(self.selected_entry_index()?)?;
+    let (feed_url, title, published) = entry_key(entry);
+    let key = (
+      feed_url,
+      title,
+      published,
+      area.width,
+      self.footnote_links,
+      self.wrap_lines,
+      entry.plain_text.is_some(),
+      self.focused_link,
+      self
+        .entry_find
+        .as_ref()
+        .map(|find| (find.query.clone(), find.case_sensitive, find.current)),
+    );
+    let mut entry_view = self.entry_view.borrow_mut();
+    if entry_view.as_ref().is_none_or(|view| view.key != key) {
+      let feed = self.entry_feed(entry);
+      // Configured query feeds, leaving out the built-in one of unread entries
+      let queries: Vec<&str> = self.list[1..]
+        .iter()
+        .filter(|f| match &f.kind {
+          FeedKind::Query(query) => {
+            query::feed_matches(&query.filter, feed)
+              && query::entry_matches(&query.filter, feed, entry)
+          }
+          _ => false,
+        })
+        .map(|f| f.title.as_str())
+        .collect();
+      let (mut entry_content, link_lines) = build_entry_content(
+        feed,
+        entry,
+        &queries,
+        self.footnote_links,
+        self.focused_link,
+        &self.theme,
+      );
+      let padding = area.width / 20;
+      // Inside the border of the app
+      let text_width = area.width.saturating_sub(2 + padding * 2);
+      // Rows the links start on, from the wrapped height of the lines before them
+      let mut links = Vec::with_capacity(link_lines.len());
+      let mut rows = 0;
+      let mut lines_counted = 0;
+      for (url, line) in link_lines {
+        for text_line in &entry_content[lines_counted..line] {
+          rows += if self.wrap_lines {
+            Paragraph::new(text_line.clone())
+              .wrap(Wrap { trim: false })
+              .line_count(text_width)
+          } else {
+            1
+          };
+        }
+        lines_counted = line;
+        links.push((url, rows));
+      }
+      let matches = match (self.entry_find_pattern(), &self.entry_find) {
+        (Some(pattern), Some(find)) => highlight_matches(
+          &mut entry_content,
+          &pattern,
+          find.current,
+          text_width,
+          self.wrap_lines,
+          &self.theme,
+        ),
+        _ => Vec::new(),
+      };
+      let mut paragraph = Paragraph::new(entry_content).block(
+        Block::default()
+          .padding(Padding::new(padding, padding, 1, 1))
+          .borders(Borders::NONE),
+      );
+      if self.wrap_lines {
+        paragraph = paragraph.wrap(Wrap { trim: false });
+      }
+      let line_count = paragraph.line_count(text_width);
+      *entry_view = Some(EntryView {
+        key,
+        paragraph,
+        line_count,
+        links,
+        matches,
+      });
+    }
+    RefMut::filter_map(entry_view, Option::as_mut).ok()
+  }
+
+  /// The find of the open entry as a pattern, `None` while nothing is typed
+  fn entry_find_pattern(&self) -> Option<Regex> {
+    let find = self.entry_find.as_ref().filter(|f| !f.query.is_empty())?;
+    RegexBuilder::new(&regex::escape(&find.query))
+      .case_insensitive(!find.case_sensitive)
+      .build()
+      .ok()
+  }
+
+  fn handle_entry_find_input(&mut self, key_event: KeyEvent) {
+    let Some(find) = self.entry_find.as_mut() else {
+      return;
+    };
+    match key_event.code {
+      KeyCode::Enter if find.query.is_empty() => self.entry_find = None,
+      KeyCode::Enter => {
+        find.editing = false;
+        self.find_next(true);
+      }
+      KeyCode::Tab => find.case_sensitive = !find.case_sensitive,
+      KeyCode::Char(c) => find.query.push(c),
+      KeyCode::Backspace => {
+        find.query.pop();
+      }
+      KeyCode::Esc => self.entry_find = None,
+      _ => {}
+    }
+  }
+
+  /// Scrolls the open entry to the next or previous match of the find, the first
+  /// one goes from the top of the screen
+  fn find_next(&mut self, forward: bool) {
+    let Some(find) = self.entry_find.as_mut() else {
+      return;
+    };
+    let view = self.entry_view.borrow();
+    let Some(matches) = view.as_ref().map(|v| &v.matches).filter(|m| !m.is_empty()) else {
+      return;
+    };
+    let scroll = self.scroll.min(self.max_scroll.get());
+    let current = match (find.current, forward) {
+      (None, true) => matches.iter().position(|&row| row >= scroll).unwrap_or(0),
+      (None, false) => matches
+        .iter()
+        .rposition(|&row| row < scroll)
+        .unwrap_or(matches.len() - 1),
+      (Some(i), true) => (i + 1) % matches.len(),
+      (Some(i), false) => i.checked_sub(1).unwrap_or(matches.len() - 1),
+    };
+    let current = current.min(matches.len() - 1);
+    let row = matches[current];
+    drop(view);
+    find.current = Some(current);
+    let height = self.entry_height.get().max(1);
+    if row < scroll || row >= scroll + height {
+      self.scroll = row.saturating_sub(height / 2).min(self.max_scroll.get());
+    }
+  }
+
+  /// Lays the open entry out for the new terminal size right away, keeping about the
+  /// same part of it in view instead of leaving the scroll clamped on the next frame
+  fn resize(&mut self, width: u16, height: u16) {
+    self.area_width = width as usize;
+    if !self.entry_open {
+      return;
+    }
+    let old_lines = self
+      .entry_view
+      .borrow()
+      .as_ref()
+      .map_or(0, |v| v.line_count);
+    let Some(new_lines) = self
+      .layout_entry(Rect::new(0, 0, width, height))
+      .map(|view| view.line_count)
+    else {
+      return;
+    };
+    let inner_height = height.saturating_sub(2) as usize;
+    let max_scroll = new_lines.saturating_sub(inner_height);
+    let anchored = (self.current_scroll() * new_lines).checked_div(old_lines);
+    self.scroll = anchored.unwrap_or(self.scroll).min(max_scroll);
+    self.max_scroll.set(max_scroll);
+    self.entry_height.set(inner_height.saturating_sub(2));
+  }
+
+  /// Where the open entry is scrolled to, a resize may have moved its bottom up
+  fn current_scroll(&self) -> usize {
+    self.scroll.min(self.max_scroll.get())
+  }
+
+  /// Scrolls the open entry by a screen, keeping one line of the previous screen in view
+  fn scroll_page(&mut self, down: bool) {
+    let page = self.entry_height.get().saturating_sub(1).max(1);
+    if down {
+      self.scroll_to(self.current_scroll().saturating_add(page));
+    } else {
+      self.scroll_to(self.current_scroll().saturating_sub(page));
+    }
+  }
+
+  /// Selects the first or last item of the list, or scrolls the open entry to its top or bottom
+  fn jump(&mut self, to_end: bool) {
+    if self.entry_open {
+      self.scroll_to(if to_end { usize::MAX } else { 0 });
+      return;
+    }
+    match self.active_list {
+      ActiveList::Tags => self.select_tag_row(if to_end { self.tags().len() } else { 0 }),
+      ActiveList::Feeds => {
+        let listed = self.listed_feeds();
+        let target = if to_end {
+          listed.last()
+        } else {
+          listed.first()
+        };
+        if let Some(&index) = target {
+          self.index = index;
+        }
+      }
+      ActiveList::Entries => {
+        let entries_len = self.listed_entries(self.index).len();
+        if entries_len > 0 {
+          self
+            .entries_state
+            .select(Some(if to_end { entries_len - 1 } else { 0 }));
+        }
+      }
+      _ => {}
+    }
+  }
+
+  fn enter(&mut self) {
+    if self.listed_feeds().is_empty() {
+      return;
+    }
+    match self.active_list {
+      ActiveList::Feeds => {
+        self.active_list = ActiveList::Entries;
+        self.read_in_view.clear();
+        let feed = &self.list[self.index];
+        let first_unread = self
+          .listed_entries(self.index)
+          .iter()
+          .position(|&i| !feed.entries[i].read);
+        match first_unread {
+          Some(unread) if self.config.auto_open_first_unread => {
+            self.entries_state.select(Some(unread));
+            self.open_entry();
+          }
+          _ => {
+            // Come back to where the feed was left, it may have shrunk since
+            let entries_len = self.listed_entries(self.index).len();
+            let selected = self
+              .entry_selections
+              .get(&selection_key(feed))
+              .map_or(0, |&row| row.min(entries_len.saturating_sub(1)));
+            self.entries_state.select(Some(selected));
+          }
+        }
+      }
+      ActiveList::Entries => self.open_entry(),
+      ActiveList::Tags => self.active_list = ActiveList::Feeds,
+      ActiveList::Entry => self.open_focused_link(),
+    }
+  }
+
+  /// Selects the next or previous unread entry, going on to the closest listed feed
+  /// with one, and opens it if an entry is open
+  fn jump_to_unread(&mut self, forward: bool) {
+    let listed = self.listed_feeds();
+    let Some(position) = listed.iter().position(|&i| i == self.index) else {
+      return;
+    };
+    // From the feeds list the whole feed counts
+    let selected = match self.active_list {
+      ActiveList::Entries | ActiveList::Entry => self.entries_state.selected(),
+      ActiveList::Tags | ActiveList::Feeds => None,
+    };
+    let mut found = self
+      .unread_row(self.index, selected, forward)
+      .map(|row| (self.index, row));
+    let count = listed.len();
+    let steps = if self.config.wrap_navigation {
+      count
+    } else if forward {
+      count - position - 1
+    } else {
+      position
+    };
+    for step in 1..=steps {
+      if found.is_some() {
+        break;
+      }
+      let index = if forward {
+        listed[(position + step) % count]
+      } else {
+        listed[(position + count - step) % count]
+      };
+      // Muted feeds don't count towards unread, unless there's nowhere else to go
+      if self.list[index].muted && index != self.index {
+        continue;
+      }
+      found = self
+        .unread_row(index, None, forward)
+        .map(|row| (index, row));
+    }
+    let Some((index, row)) = found else {
+      self.message = Some("No unread entries".to_string());
+      return;
+    };
+    if self.entry_open {
+      self.save_scroll_position();
+    }
+    if index != self.index {
+      if let (Some(feed), Some(selected)) = (self.list.get(self.index), selected) {
+        self.entry_selections.insert(selection_key(feed), selected);
+      }
+      self.index = index;
+      self.read_in_view.clear();
+    }
+    self.entries_state.select(Some(row));
+    if self.entry_open {
+      self.open_entry();
+    } else {
+      self.active_list = ActiveList::Entries;
+    }
+  }
+
+  /// Row of the first unread entry of a feed after `from`, or the last one before it,
+  /// where no `from` stands for the whole feed
+  fn unread_row(&self, feed_index: usize, from: Option<usize>, forward: bool) -> Option<usize> {
+    let feed = &self.list[feed_index];
+    let listed = self.listed_entries(feed_index);
+    let mut rows = listed.iter().enumerate();
+    let unread = |(_, &i): &(usize, &usize)| !feed.entries[i].read;
+    let found = if forward {
+      rows.find(|row| from.is_none_or(|from| row.0 > from) && unread(row))
+    } else {
+      rows.rfind(|row| from.is_none_or(|from| row.0 < from) && unread(row))
+    };
+    found.map(|(row, _)| row)
+  }
+
+  /// Index into the current feed's entries of the selected row in the entries list
+  fn selected_entry_index(&self) -> Option<usize> {
+    let selected = self.entries_state.selected()?;
+    self.listed_entries(self.index).get(selected).copied()
+  }
+
+  /// Opens the selected entry in the reading view and marks it as read, unless that
+  /// waits until it's scrolled to the bottom
+  fn open_entry(&mut self) {
+    self.active_list = ActiveList::Entry;
+    self.scroll = 0;
+    self.horizontal_scroll = 0;
+    self.focused_link = None;
+    self.entry_find = None;
+    self.entry_open = true;
+    if let Some(selected) = self.selected_entry_index() {
+      self.read_in_view.push(selected);
+      if let Some(entry) = self.list[self.index].entries.get(selected) {
+        let key = entry_key(entry);
+        self.new_entries.remove(&key);
+        let (feed_url, title, published) = key;
+        // Clamped to the laid out text when it's drawn
+        match self
+          .cache
+          .load_scroll_position(&feed_url, &title, published.as_deref())
+        {
+          Ok(position) => self.scroll = position,
+          Err(e) => self.message = Some(format!("Failed to load reading position: {}", e)),
+        }
+        if !self.config.mark_read_on_scroll_end {
+          self.mark_open_entry_read();
+        }
+      }
+    }
+    self.furthest_scroll.set(self.scroll);
+  }
+
+  fn mark_open_entry_read(&mut self) {
+    let Some(entry) = self
+      .selected_entry_index()
+      .and_then(|i| self.list.get(self.index)?.entries.get(i))
+    else {
+      return;
+    };
+    let (feed_url, title, published) = entry_key(entry);
+    if let Err(e) = self
+      .cache
+      .mark_entry_read(&feed_url, &title, published.as_deref())
+    {
+      self.message = Some(format!("Failed to save read state: {}", e));
+    }
+    self.sync_read_state(&feed_url, &title, published.as_deref());
+  }
+
+  /// Marks the open entry read once its text was drawn scrolled to the bottom, which
+  /// an entry that fits on the screen is right away
+  fn mark_read_at_bottom(&mut self) {
+    if !self.entry_open || self.current_scroll() < self.max_scroll.get() {
+      return;
+    }
+    let unread = self
+      .selected_entry_index()
+      .and_then(|i| self.list.get(self.index)?.entries.get(i))
+      .is_some_and(|entry| !entry.read && entry.plain_text.is_some());
+    if unread {
+      self.mark_open_entry_read();
+    }
+  }
+
+  /// Stores how far the open entry was read
+  fn save_scroll_position(&mut self) {
+    let Some(entry) = self
+      .selected_entry_index()
+      .and_then(|i| self.list.get(self.index)?.entries.get(i))
+    else {
+      return;
+    };
+    let (feed_url, title, published) = entry_key(entry);
+    if let Err(e) = self.cache.save_scroll_position(
+      &feed_url,
+      &title,
+      published.as_deref(),
+      self.furthest_scroll.get(),
+    ) {
+      self.message = Some(format!("Failed to save reading position: {}", e));
+    }
+  }
+
+  /// Reads the text of the open entry from the cache if it was loaded without it,
+  /// returning whether there was anything to load
+  fn load_entry_body(&mut self) -> bool {
+    if !self.entry_open {
+      return false;
+    }
+    let Some(entry) = self
+      .selected_entry_index()
+      .and_then(|i| self.list.get(self.index)?.entries.get(i))
+      .filter(|entry| entry.plain_text.is_none())
+    else {
+      return false;
+    };
+    let (feed_url, title, published) = entry_key(entry);
+    let text = match self
+      .cache
+      .load_entry_body(&feed_url, &title, published.as_deref())
+    {
+      Ok(text) => text.unwrap_or_default(),
+      Err(e) => {
+        self.message = Some(format!("Failed to load the entry: {}", e));
+        String::new()
+      }
+    };
+    self.sync_entry(&feed_url, &title, published.as_deref(), |entry| {
+      entry.plain_text = Some(text.clone())
+    });
+    true
+  }
+
+  /// Marks an entry read in every feed listing it, search results hold copies of entries
+  fn sync_read_state(&mut self, feed_url: &str, title: &str, published: Option<&str>) {
+    self.sync_entry(feed_url, title, published, |entry| entry.read = true);
+  }
+
+  /// Applies `update` to every copy of an entry across the listed feeds
+  fn sync_entry(
+    &mut self,
+    feed_url: &str,
+    title: &str,
+    published: Option<&str>,
+    update: impl Fn(&mut FeedEntry),
+  ) {
+    for entry in self.list.iter_mut().flat_map(|f| f.entries.iter_mut()) {
+      if entry.feed_url == feed_url
+        && entry.title == title
+        && entry.published.as_deref() == published
+      {
+        update(entry);
+      }
+    }
+  }
+
+  /// Opens the first audio or video enclosure of the selected entry in the default player
+  fn play_enclosure(&mut self) {
+    let Some(entry) = self
+      .selected_entry_index()
+      .and_then(|i| self.list.get(self.index)?.entries.get(i))
+    else {
+      return;
+    };
+    let playable = entry
+      .enclosures
+      .iter()
+      .find(|e| matches!(e.kind(), EnclosureKind::Audio | EnclosureKind::Video));
+    match playable {
+      Some(enclosure) => {
+        if let Err(e) = open::that_detached(&enclosure.url) {
+          self.message = Some(format!("Failed to open {}: {}", enclosure.url, e));
+        }
+      }
+      None => self.message = Some("The entry has no audio or video".to_string()),
+    }
+  }
+
+  /// Moves the focus to the next or previous link in the body of the open entry,
+  /// scrolling it into view
+  fn focus_link(&mut self, forward: bool) {
+    if !self.entry_open {
+      return;
+    }
+    let view = self.entry_view.borrow();
+    let Some(links) = view.as_ref().map(|v| &v.links).filter(|l| !l.is_empty()) else {
+      drop(view);
+      self.message = Some("The entry has no links in its text".to_string());
+      return;
+    };
+    let focused = match (self.focused_link, forward) {
+      (None, true) => 0,
+      (None, false) => links.len() - 1,
+      (Some(i), true) => (i + 1) % links.len(),
+      (Some(i), false) => i.checked_sub(1).unwrap_or(links.len() - 1),
+    };
+    let row = links[focused].1;
+    drop(view);
+    self.focused_link = Some(focused);
+    let height = self.entry_height.get().max(1);
+    let scroll = self.scroll.min(self.max_scroll.get());
+    if row < scroll || row >= scroll + height {
+      self.scroll = row.saturating_sub(height / 2).min(self.max_scroll.get());
+    }
+  }
+
+  /// Opens the focused link of the open entry in the browser
+  fn open_focused_link(&mut self) {
+    let Some(focused) = self.focused_link else {
+      return;
+    };
+    let view = self.entry_view.borrow();
+    let Some((url, _)) = view.as_ref().and_then(|v| v.links.get(focused)) else {
+      return;
+    };
+    let url = url.clone();
+    drop(view);
+    if let Err(e) = open::that_detached(&url) {
+      self.message = Some(format!("Failed to open {}: {}", url, e));
+    }
+  }
+
+  /// Copies the first web link of the selected entry to the clipboard
+  fn copy_link(&mut self) {
+    let Some(entry) = self
+      .selected_entry_index()
+      .and_then(|i| self.list.get(self.index)?.entries.get(i))
+    else {
+      return;
+    };
+    let Some(link) = entry
+      .links
+      .iter()
+      .find(|l| l.starts_with("https://") || l.starts_with("http://"))
+    else {
+      self.message = Some("The entry has no link".to_string());
+      return;
+    };
+    let link = link.clone();
+    let copied = match &mut self.clipboard {
+      Some(Clipboard(clipboard)) => clipboard.set_text(link),
+      None => arboard::Clipboard::new().and_then(|mut clipboard| {
+        clipboard.set_text(link)?;
+        self.clipboard = Some(Clipboard(clipboard));
+        Ok(())
+      }),
+    };
+    match copied {
+      Ok(()) => self.notice = Some(("Link copied".to_string(), Instant::now())),
+      Err(e) => self.message = Some(format!("Failed to copy the link: {}", e)),
+    }
+  }
+
+  /// Stars or unstars the selected entry
+  fn toggle_starred(&mut self) {
+    let Some(entry) = self
+      .selected_entry_index()
+      .and_then(|i| self.list.get(self.index)?.entries.get(i))
+    else {
+      return;
+    };
+    let starred = !entry.starred;
+    let (feed_url, title, published) = entry_key(entry);
+    let result = if starred {
+      self
+        .cache
+        .mark_entry_starred(&feed_url, &title, published.as_deref())
+    } else {
+      self
+        .cache
+        .mark_entry_unstarred(&feed_url, &title, published.as_deref())
+    };
+    if let Err(e) = result {
+      self.message = Some(format!("Failed to save the star: {}", e));
+    }
+    self.sync_entry(&feed_url, &title, published.as_deref(), |entry| {
+      entry.starred = starred
+    });
+  }
+
+  /// The subscription an entry was fetched from, falling back to the open feed
+  fn entry_feed(&self, entry: &FeedEntry) -> &Feed {
+    self
+      .list
+      .iter()
+      .find(|f| f.kind == FeedKind::Subscription && f.url == entry.feed_url)
+      .unwrap_or(&self.list[self.index])
+  }
+
+  fn back(&mut self) {
+    match self.active_list {
+      ActiveList::Entry => {
+        self.save_scroll_position();
+        self.active_list = ActiveList::Entries;
+        self.entry_open = false;
+        self.entry_find = None;
+      }
+      ActiveList::Entries => {
+        if let (Some(feed), Some(selected)) =
+          (self.list.get(self.index), self.entries_state.selected())
+        {
+          self.entry_selections.insert(selection_key(feed), selected);
+        }
+        self.active_list = ActiveList::Feeds;
+        self.read_in_view.clear();
+        self.clamp_feed_selection();
+      }
+      ActiveList::Feeds if self.show_tags => self.active_list = ActiveList::Tags,
+      _ => {}
+    }
+  }
+
+  fn handle_bulk_input(&mut self, key_event: KeyEvent) {
+    let Some(input) = self.bulk_input.as_mut() else {
+      return;
+    };
+    match key_event.code {
+      KeyCode::Char('d') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+        let input = self.bulk_input.take().unwrap_or_default();
+        self.bulk_subscribe(&input);
+      }
+      KeyCode::Char(c) => input.push(c),
+      KeyCode::Enter => input.push('\n'),
+      KeyCode::Backspace => {
+        input.pop();
+      }
+      KeyCode::Esc => self.bulk_input = None,
+      _ => {}
+    }
+  }
+
+  fn handle_add_feed_input(&mut self, key_event: KeyEvent) {
+    let Some(dialog) = self.add_feed.as_mut() else {
+      return;
+    };
+    if dialog.checking {
+      if key_event.code == KeyCode::Esc {
+        self.add_feed = None;
+      }
+      return;
+    }
+    let fields = dialog.fields.len();
+    match key_event.code {
+      KeyCode::Enter => self.add_feed(),
+      KeyCode::Tab | KeyCode::Down => dialog.focus = (dialog.focus + 1) % fields,
+      KeyCode::BackTab | KeyCode::Up => dialog.focus = (dialog.focus + fields - 1) % fields,
+      KeyCode::Char(c) => dialog.fields[dialog.focus].push(c),
+      KeyCode::Backspace => {
+        dialog.fields[dialog.focus].pop();
+      }
+      KeyCode::Esc => self.add_feed = None,
+      _ => {}
+    }
+  }
+
+  /// Fetches the feed typed into the add feed popup in the background, the popup waits
+  /// for it and stays open with the error if it can't be subscribed to
+  fn add_feed(&mut self) {
+    let Some(dialog) = self.add_feed.as_mut() else {
+      return;
+    };
+    let [url, name, tags] = dialog.fields.each_ref().map(|f| f.trim());
+    let tags: Vec<String> = tags
+      .split(',')
+      .map(str::trim)
+      .filter(|t| !t.is_empty())
+      .map(str::to_string)
+      .collect();
+    let feed = Feeds {
+      link: url.to_string(),
+      name: (!name.is_empty()).then(|| name.to_string()),
+      tags: (!tags.is_empty()).then_some(tags),
+      ..Default::default()
+    };
+    dialog.error = if url.is_empty() {
+      Some("Enter the URL of the feed".to_string())
+    } else if self.list.iter().any(|f| f.url == feed.link) {
+      Some("Already subscribed".to_string())
+    } else {
+      None
+    };
+    if dialog.error.is_some() {
+      return;
+    }
+    dialog.checking = true;
+    let client = self.client.clone();
+    let tx = self.updates_tx.clone();
+    tokio::spawn(async move {
+      let result = feeds::validate_feed(&client, &feed).await;
+      let _ = tx.send(FeedUpdate::Validated { feed, result });
+    });
+  }
+
+  /// Subscribes to the feed of the add feed popup once it's fetched, unless the popup
+  /// was closed in the meantime
+  fn feed_validated(&mut self, feed: Feeds, result: Result<String, String>) {
+    let Some(dialog) = self
+      .add_feed
+      .as_mut()
+      .filter(|d| d.checking && d.fields[0].trim() == feed.link)
+    else {
+      return;
+    };
+    dialog.checking = false;
+    match result.and_then(|body| self.subscribe(feed, &body)) {
+      Ok(title) => {
+        self.add_feed = None;
+        self.message = Some(format!("Subscribed to {}", title));
+      }
+      Err(e) => {
+        if let Some(dialog) = self.add_feed.as_mut() {
+          dialog.error = Some(e);
+        }
+      }
+    }
+  }
+
+  fn handle_search_input(&mut self, key_event: KeyEvent) {
+    let Some(input) = self.search_input.as_mut() else {
+      return;
+    };
+    match key_event.code {
+      KeyCode::Enter => {
+        let query = self.search_input.take().unwrap_or_default();
+        self.search(&query);
+      }
+      KeyCode::Char(c) => input.push(c),
+      KeyCode::Backspace => {
+        input.pop();
+      }
+      KeyCode::Esc => self.search_input = None,
+      _ => {}
+    }
+  }
+
+  /// Replaces the search results feed with the entries matching `query` and opens it.
+  /// An empty query only removes the previous results.
+  fn search(&mut self, query: &str) {
+    let query = query.trim();
+    let results_open = matches!(
+      self.list.get(self.index).map(|f| &f.kind),
+      Some(FeedKind::Search(_))
+    );
+    self.list.retain(|f| !matches!(f.kind, FeedKind::Search(_)));
+    self.index = self.index.min(self.list.len().saturating_sub(1));
+    if results_open {
+      self.active_list = ActiveList::Feeds;
+      self.read_in_view.clear();
+      self.clamp_feed_selection();
+    }
+    if query.is_empty() {
+      return;
+    }
+    // Search the text of cached entries too
+    for feed in self
+      .list
+      .iter_mut()
+      .filter(|f| f.kind == FeedKind::Subscription)
+    {
+      if let Err(e) = self.cache.load_texts(feed) {
+        self.message = Some(format!("Failed to load entries of {}: {}", feed.title, e));
+      }
+    }
+    let results = Feed::search(
+      self
+        .list
+        .iter()
+        .filter(|f| f.kind == FeedKind::Subscription),
+      query,
+    );
+    if results.entries.is_empty() {
+      self.message = Some(format!("No entries match \"{}\"", query));
+      return;
+    }
+    self.list.push(results);
+    self.index = self.list.len() - 1;
+    self.active_list = ActiveList::Entries;
+    self.read_in_view.clear();
+    self.entries_state.select(Some(0));
+  }
+
+  /// Selects the entry with the typed 1-based number, clamped to the entry count
+  fn jump_to_pending_number(&mut self) {
+    let Some((digits, _)) = self.pending_number.take() else {
+      return;
+    };
+    let entries_len = self.listed_entries(self.index).len();
+    if let Ok(number) = digits.parse::<usize>() {
+      if entries_len > 0 {
+        self
+          .entries_state
+          .select(Some(number.clamp(1, entries_len) - 1));
+      }
+    }
+  }
+
+  fn bulk_subscribe(&mut self, input: &str) {
+    let mut urls: Vec<&str> = Vec::new();
+    for url in input.lines().map(str::trim).filter(|l| !l.is_empty()) {
+      if !urls.contains(&url) {
+        urls.push(url);
+      }
+    }
+
+    // Feeds already subscribed to aren't fetched, `feeds_validated` reports them
+    let feeds: Vec<Feeds> = urls
+      .iter()
+      .filter(|&&url| !self.list.iter().any(|f| f.url == url))
+      .map(|url| Feeds {
+        link: url.to_string(),
+        ..Default::default()
+      })
+      .collect();
+    self.bulk_checking = Some(urls.into_iter().map(str::to_string).collect());
+    tokio::spawn(feeds::validate_feeds(
+      self.client.clone(),
+      feeds,
+      self.config.fetch_concurrency,
+      self.updates_tx.clone(),
+    ));
+  }
+
+  /// Subscribes to the fetched feeds of the bulk subscribe and lists the outcome for
+  /// every URL, unless it was cancelled in the meantime
+  fn feeds_validated(&mut self, validated: Vec<(Feeds, Result<String, String>)>) {
+    let Some(urls) = self
+      .bulk_checking
+      .take_if(|urls| validated.iter().all(|(feed, _)| urls.contains(&feed.link)))
+    else {
+      return;
+    };
+    let mut validated: HashMap<String, (Feeds, Result<String, String>)> = validated
+      .into_iter()
+      .map(|(feed, result)| (feed.link.clone(), (feed, result)))
+      .collect();
+    let mut results = Vec::new();
+    for url in urls {
+      let result = match validated.remove(&url) {
+        Some((feed, result)) => result.and_then(|body| self.subscribe(feed, &body)),
+        None => Err("Already subscribed".to_string()),
+      };
+      results.push((url, result.map(|_| ())));
+    }
+    self.bulk_results = Some(results);
+  }
+
+  /// Adds a fetched feed to urls.toml, the cache and the list, `body` being what
+  /// `validate_feed` returned for it. Returns the title of the feed.
+  fn subscribe(&mut self, feed: Feeds, body: &str) -> Result<String, String> {
+    // It may have been subscribed to while it was fetched
+    if self.list.iter().any(|f| f.url == feed.link) {
+      return Err("Already subscribed".to_string());
+    }
+    config::append_feed(&feed).map_err(|e| format!("Failed to update urls.toml: {}", e))?;
+    // The new feed is last in urls.toml
+    let position = config::parse_feed_urls()?.len() - 1;
+    let mut feed = feeds::parse_feed(body, &feed, self.area_width, self.config.max_parsed_entries)?;
+    feed.position = position;
+    let saved = self.cache.save_feed(&feed, position).map(|_| ());
+    let title = feed.title.clone();
+    // It's in urls.toml already, so it's listed either way and stored on the next refresh
+    self.insert_feed(feed);
+    self.rebuild_query_feeds();
+    saved.map_err(|e| {
+      format!(
+        "Subscribed, but failed to save the feed to the cache: {}",
+        e
+      )
+    })?;
+    Ok(title)
+  }
+
+  /// Runs a destructive action, asking for confirmation first unless disabled in the config
+  fn request(&mut self, action: DestructiveAction) {
+    if self.config.confirm_destructive {
+      self.confirm = Some(action);
+    } else {
+      self.perform(action);
+    }
+  }
+
+  fn perform(&mut self, action: DestructiveAction) {
+    let summary = match action {
+      DestructiveAction::MarkAllRead => self.cache.mark_all_read().map(|count| {
+        let unread: Vec<_> = self
+          .list
+          .iter()
+          .filter(|f| f.kind == FeedKind::Subscription)
+          .flat_map(|f| f.entries.iter().filter(|e| !e.read))
+          .map(entry_key)
+          .collect();
+        if !unread.is_empty() {
+          self.marked_read = unread;
+        }
+        for entry in self.list.iter_mut().flat_map(|f| f.entries.iter_mut()) {
+          entry.read = true;
+        }
+        self.clamp_feed_selection();
+        self.clamp_entry_selection();
+        format!("Marked {} entries as read", count)
+      }),
+      DestructiveAction::ClearCache => self
+        .list
+        .iter_mut()
+        .filter(|f| f.kind == FeedKind::Subscription)
+        // Texts not loaded yet would be gone with the cache
+        .try_for_each(|feed| self.cache.load_texts(feed))
+        .and_then(|_| self.cache.clear_all())
+        .and_then(|(entries, feeds)| {
+          // Store the feeds currently shown again so read state keeps being tracked
+          for feed in self.list.iter_mut() {
+            for entry in &mut feed.entries {
+              entry.read = false;
+              entry.starred = false;
+            }
+            if feed.kind == FeedKind::Subscription {
+              self.cache.save_feed(feed, feed.position)?;
+            }
+          }
+          Ok(format!(
+            "Cleared {} entries across {} feeds",
+            entries, feeds
+          ))
+        }),
+      DestructiveAction::DeleteFeed(url, title) => {
+        self.message = Some(match self.delete_feed(&url) {
+          Ok(()) => format!("Unsubscribed from {}", title),
+          Err(e) => e,
+        });
+        return;
+      }
+    };
+    self.message = Some(summary.unwrap_or_else(|e| format!("Cache operation failed: {}", e)));
+  }
+
+  /// Asks to unsubscribe from the selected feed, only subscriptions can be deleted
+  fn request_delete_feed(&mut self) {
+    if !matches!(self.active_list, ActiveList::Feeds) {
+      return;
+    }
+    let Some(feed) = self.list.get(self.index) else {
+      return;
+    };
+    match feed.kind {
+      FeedKind::Subscription => {
+        let action = DestructiveAction::DeleteFeed(feed.url.clone(), feed.title.clone());
+        self.request(action);
+      }
+      FeedKind::Search(_) => {
+        self.message = Some("Search results close with an empty search".to_string());
+      }
+      FeedKind::Query(_) => {
+        self.message = Some("Query feeds are removed from urls.toml".to_string());
+      }
+    }
+  }
+
+  /// Removes a subscription from urls.toml, the cache and the list
+  fn delete_feed(&mut self, url: &str) -> Result<(), String> {
+    let Some(index) = self.list.iter().position(|f| f.url == url) else {
+      return Ok(());
+    };
+    config::remove_feed(url).map_err(|e| format!("Failed to update urls.toml: {}", e))?;
+    let cache_error = |e: rusqlite::Error| format!("Cache operation failed: {}", e);
+    self.cache.delete_feed(url).map_err(cache_error)?;
+    let position = self.list[index].position;
+    self.forget_feed(url, position).map_err(cache_error)
+  }
+
+  /// Drops a feed that's gone from urls.toml from the list, if it's listed, and moves
+  /// the feeds after its `position` up
+  fn forget_feed(&mut self, url: &str, position: usize) -> rusqlite::Result<()> {
+    if let Some(index) = self.list.iter().position(|f| f.url == url) {
+      self.list.remove(index);
+      if self.index > index {
+        self.index -= 1;
+      }
+    }
+    for feed in &mut self.list {
+      if feed.kind == FeedKind::Subscription && feed.position > position {
+        feed.position -= 1;
+      }
+    }
+    let positions: Vec<(&str, usize)> = self
+      .list
+      .iter()
+      .filter(|f| f.kind == FeedKind::Subscription)
+      .map(|f| (f.url.as_str(), f.position))
+      .collect();
+    self.cache.update_positions(&positions)?;
+    self.rebuild_query_feeds();
+    self.clamp_feed_selection();
+    Ok(())
+  }
+
+  /// Marks every entry of the selected feed as read
+  fn mark_feed_read(&mut self) {
+    let Some(feed) = self.list.get(self.index) else {
+      return;
+    };
+    let unread: Vec<_> = feed
+      .entries
+      .iter()
+      .filter(|e| !e.read)
+      .map(entry_key)
+      .collect();
+    // Search results and query feeds span several feeds, so their entries are stored one by one
+    let result = match feed.kind {
+      FeedKind::Subscription => self.cache.mark_feed_read(&feed.url).map(|_| ()),
+      FeedKind::Search(_) | FeedKind::Query(_) => {
+        unread.iter().try_for_each(|(url, title, published)| {
+          self.cache.mark_entry_read(url, title, published.as_deref())
+        })
+      }
+    };
+    if let Err(e) = result {
+      self.message = Some(format!("Failed to save read state: {}", e));
+    }
+    for (url, title, published) in &unread {
+      self.sync_read_state(url, title, published.as_deref());
+    }
+    if !unread.is_empty() {
+      self.marked_read = unread;
+    }
+    self.clamp_entry_selection();
+  }
+
+  /// Marks the entries of the last mark of a feed or everything as read unread again
+  fn undo_mark_read(&mut self) {
+    if self.marked_read.is_empty() {
+      self.message = Some("Nothing marked as read to undo".to_string());
+      return;
+    }
+    let entries = std::mem::take(&mut self.marked_read);
+    if let Err(e) = self.cache.mark_entries_unread(&entries) {
+      self.message = Some(format!("Failed to save read state: {}", e));
+      self.marked_read = entries;
+      return;
+    }
+    let keys: HashSet<_> = entries.into_iter().collect();
+    for entry in self.list.iter_mut().flat_map(|f| f.entries.iter_mut()) {
+      if keys.contains(&entry_key(entry)) {
+        entry.read = false;
+      }
+    }
+    // Unread entries show up in the query feeds again
+    self.rebuild_query_feeds();
+    self.message = Some(format!("Marked {} entries as unread again", keys.len()));
+  }
+
+  /// Layout last chosen with `v` for terminals of this size, or the configured one
+  fn layout_for(&mut self, bucket: SizeBucket) -> bool {
+    match self.cache.load_state(&layout_state_key(bucket)) {
+      Ok(Some(layout)) => layout == "split",
+      Ok(None) => self.config.split_view_for(bucket),
+      Err(e) => {
+        self.message = Some(format!("Failed to load the layout: {}", e));
+        self.config.split_view_for(bucket)
+      }
+    }
+  }
+
+  /// Switches between the split and single pane layout, remembering it for terminals of
+  /// the current size
+  fn toggle_split_view(&mut self) {
+    self.split_view = !self.split_view;
+    let Some(bucket) = self.size_bucket else {
+      return;
+    };
+    let layout = if self.split_view { "split" } else { "single" };
+    if let Err(e) = self
+      .cache
+      .save_state(&layout_state_key(bucket), Some(layout))
+    {
+      self.message = Some(format!("Failed to save the layout: {}", e));
+    }
+  }
+
+  fn toggle_mute(&mut self) {
+    if let Some(feed) = self
+      .list
+      .get_mut(self.index)
+      .filter(|f| f.kind == FeedKind::Subscription)
+    {
+      feed.muted = !feed.muted;
+      // Keep the in-memory state even if the config can't be written, it's lost on restart
+      if let Err(e) = config::set_feed_muted(&feed.url, feed.muted) {
+        self.message = Some(format!("Failed to update urls.toml: {}", e));
+      }
+      self.rebuild_query_feeds();
+    }
+  }
+
+  /// Swaps the selected subscription with the one listed above or below it, in urls.toml
+  /// and the cache
+  fn move_feed(&mut self, down: bool) {
+    if !matches!(self.active_list, ActiveList::Feeds) {
+      return;
+    }
+    let listed = self.listed_feeds();
+    let Some(position) = listed.iter().position(|&i| i == self.index) else {
+      return;
+    };
+    let neighbor = if down {
+      listed.get(position + 1)
+    } else {
+      position.checked_sub(1).map(|p| &listed[p])
+    };
+    let Some(&neighbor) = neighbor else {
+      return;
+    };
+    let (feed, other) = (&self.list[self.index], &self.list[neighbor]);
+    if feed.kind != FeedKind::Subscription || other.kind != FeedKind::Subscription {
+      return;
+    }
+    // Feeds only move within their group
+    if self.config.group_by_tag && group_tag(feed) != group_tag(other) {
+      return;
+    }
+    let url = feed.url.clone();
+    if let Err(e) = config::swap_feeds(&url, &other.url) {
+      self.message = Some(format!("Failed to update urls.toml: {}", e));
+      return;
+    }
+
+    // Positions are indexes into urls.toml, which also lists feeds that failed to load
+    let links: Vec<String> = match config::parse_feed_urls() {
+      Ok(feeds) => feeds.into_iter().map(|f| f.link).collect(),
+      Err(e) => {
+        self.message = Some(e);
+        return;
+      }
+    };
+    let subscriptions = 1
+      + self.list[1..]
+        .iter()
+        .take_while(|f| f.kind == FeedKind::Subscription)
+        .count();
+    for feed in &mut self.list[1..subscriptions] {
+      if let Some(position) = links.iter().position(|l| *l == feed.url) {
+        feed.position = position;
+      }
+    }
+    self.list[1..subscriptions].sort_by_key(|f| f.position);
+    if let Some(index) = self.list.iter().position(|f| f.url == url) {
+      self.index = index;
+    }
+    let positions: Vec<(&str, usize)> = self.list[1..subscriptions]
+      .iter()
+      .map(|f| (f.url.as_str(), f.position))
+      .collect();
+    if let Err(e) = self.cache.update_positions(&positions) {
+      self.message = Some(format!("Failed to save the feed order: {}", e));
+    }
+  }
+
+  fn help(&mut self) {
+    self.show_help = !self.show_help;
+  }
+
+  /// Opens the popup of how fetching each feed has gone, as the cache has it now
+  fn show_diagnostics(&mut self) {
+    match self.cache.feed_health() {
+      Ok(health) => {
+        self.feed_health = health;
+        self.diagnostics_scroll = Some(0);
+      }
+      Err(e) => self.message = Some(format!("Failed to load feed health: {}", e)),
+    }
+  }
+
+  /// Lines of the diagnostics popup, the feeds failing the longest first
+  fn diagnostics_lines(&self) -> Vec<Line<'_>> {
+    let theme = &self.theme;
+    let now = Utc::now();
+    let unknown = FeedHealth::default();
+    let mut feeds: Vec<(&Feed, &FeedHealth)> = self
+      .list
+      .iter()
+      .filter(|f| f.kind == FeedKind::Subscription)
+      .map(|f| (f, self.feed_health.get(&f.url).unwrap_or(&unknown)))
+      .collect();
+    // Stable, so feeds with as many failures stay in list order
+    feeds.sort_by_key(|(_, health)| std::cmp::Reverse(health.consecutive_failures));
+    let mut lines = vec![Line::from(format!(
+      " {:>5}  {:>12}  {:>9}  Feed",
+      "Fails", "Last success", "Entries"
+    ))
+    .fg(theme.label)];
+    for (feed, health) in feeds {
+      let last_success = health
+        .last_success
+        .map_or_else(|| "never".to_string(), |date| time_ago(date, now));
+      let average = health
+        .average_entries
+        .map_or_else(|| "-".to_string(), |average| format!("{:.1}", average));
+      let line = Line::from(format!(
+        " {:>5}  {:>12}  {:>9}  {}",
+        health.consecutive_failures, last_success, average, feed.title
+      ));
+      lines.push(if health.consecutive_failures >= FAILING_FEED_FAILURES {
+        line.fg(theme.error)
+      } else {
+        line
+      });
+      if let Some(error) = self.fetch_errors.get(&feed.url) {
+        let error = error.lines().next().unwrap_or_default();
+        lines.push(Line::from(format!("{:35}{}", "", error)).fg(theme.muted));
+      }
+    }
+    lines
+  }
+
+  fn save_entry(&mut self) {
+    if !self.entry_open {
+      return;
+    }
+    let Some(entry) = self
+      .selected_entry_index()
+      .and_then(|i| self.list.get(self.index)?.entries.get(i))
+    else {
+      return;
+    };
+    let feed = self.entry_feed(entry);
+    let dir = self.config.save_dir();
+    self.message = Some(
+      match save::save_entry(feed, entry, &dir, &self.config.save_format) {
+        Ok(path) => format!("Saved to {}", path.display()),
+        Err(e) => format!("Failed to save the entry to {}: {}", dir.display(), e),
+      },
+    );
+  }
+}
+
+impl Widget for &App {
+  fn render(self, area: Rect, buf: &mut Buffer) {
+    let theme = &self.theme;
+    let title = Title::from(" Shinbun ".bold().fg(theme.title));
+    let instructions = Title::from(Line::from(vec![" Quit ".into(), "<q> ".bold()]));
+    let mut block = Block::default()
+      .title(title.alignment(Alignment::Left))
+      .title(
+        instructions
+          .alignment(Alignment::Left)
+          .position(block::Position::Bottom),
+      )
+      .title_bottom(Line::from(" Help <?> ".fg(theme.border)).right_aligned())
+      .borders(Borders::ALL)
+      .border_style(Style::new().fg(theme.border))
+      .border_set(border::PLAIN);
+
+    let inner_area = block.inner(area);
+    let mut entry_view = self.entry_open.then(|| self.layout_entry(area)).flatten();
+    if let Some(view) = &entry_view {
+      block = block.title_bottom(
+        Line::from(reading_progress(view, self.scroll, inner_area.height).fg(theme.border))
+          .centered(),
+      );
+      if let Some(find) = self.entry_find.as_ref().filter(|f| !f.editing) {
+        let status = match (view.matches.len(), find.current) {
+          (0, _) => format!(" {}: no matches ", find.query).fg(theme.error),
+          (count, Some(current)) => {
+            format!(" {}: {}/{} ", find.query, current.min(count - 1) + 1, count).fg(theme.title)
+          }
+          (count, None) => format!(" {}: {} matches ", find.query, count).fg(theme.title),
+        };
+        block = block.title_bottom(Line::from(status));
+      }
+    }
+    block.render(area, buf);
+    // Lists that aren't drawn this frame can't be clicked
+    self.tags_list_area.set(Default::default());
+    self.feeds_list_area.set(Default::default());
+    self.entries_list_area.set(Default::default());
+    if self.entry_open {
+      if let Some(view) = &mut entry_view {
+        let max_scroll = view.line_count.saturating_sub(inner_area.height as usize);
+        self
+          .entry_height
+          .set(inner_area.height.saturating_sub(2) as usize);
+        self.max_scroll.set(max_scroll);
+        let max_horizontal_scroll = if self.wrap_lines {
+          0
+        } else {
+          view
+            .paragraph
+            .line_width()
+            .saturating_sub(inner_area.width as usize)
+        };
+        self.max_horizontal_scroll.set(max_horizontal_scroll);
+        let scroll = (
+          self.scroll.min(max_scroll) as u16,
+          self.horizontal_scroll.min(max_horizontal_scroll) as u16,
+        );
+        self
+          .furthest_scroll
+          .set(self.furthest_scroll.get().max(scroll.0 as usize));
+        // Scrolling takes the paragraph by value, it goes back into the layout after
+        let paragraph = std::mem::take(&mut view.paragraph).scroll(scroll);
+        paragraph.render_ref(inner_area, buf);
+        view.paragraph = paragraph;
+      }
+    } else {
+      let [lists_area, status_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .areas(inner_area);
+      self.render_status_bar(status_area, buf);
+      let lists_area = if self.show_tags {
+        let [tags_area, lists_area] = Layout::default()
+          .direction(Direction::Horizontal)
+          .constraints([
+            Constraint::Length(self.tags_width().min(lists_area.width / 3)),
+            Constraint::Min(0),
+          ])
+          .areas(lists_area);
+        self.render_tags(tags_area, buf);
+        lists_area
+      } else {
+        lists_area
+      };
+      // Render the lists, side by side or only the active one
+      let (feeds_area, entries_area) = if self.split_view {
+        let horizontal_split = Layout::default()
+          .direction(Direction::Horizontal)
+          .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+          .split(lists_area);
+        (Some(horizontal_split[0]), Some(horizontal_split[1]))
+      } else {
+        match self.active_list {
+          ActiveList::Tags | ActiveList::Feeds => (Some(lists_area), None),
+          _ => (None, Some(lists_area)),
+        }
+      };
+      if let Some(feeds_area) = feeds_area {
+        self.render_feeds(feeds_area, buf);
+      }
+      if let Some(entries_area) = entries_area {
+        self.render_entries(entries_area, buf);
+      }
+    }
+
+    if let Some(input) = &self.search_input {
+      render_search_line(input, theme, inner_area, buf);
+    }
+    if let Some(find) = self.entry_find.as_ref().filter(|f| f.editing) {
+      render_find_line(find, theme, inner_area, buf);
+    }
+
+    if let Some(input) = &self.bulk_input {
+      render_bulk_input_popup(input, theme, area, buf);
+    }
+    if let Some(urls) = &self.bulk_checking {
+      render_bulk_checking_popup(urls, theme, area, buf);
+    }
+    if let Some(results) = &self.bulk_results {
+      render_bulk_results_popup(results, theme, area, buf);
+    }
+    if let Some(dialog) = &self.add_feed {
+      render_add_feed_popup(dialog, theme, area, buf);
+    }
+    render_loading_popup(&self.loading_state, theme, area, buf);
+    if let Some(action) = &self.confirm {
+      render_message_popup(
+        " Confirm ",
+        &format!("{} (y/n)", action.prompt()),
+        theme,
+        area,
+        buf,
+      );
+    }
+    if let Some(message) = &self.message {
+      render_message_popup(" Shinbun ", message, theme, area, buf);
+    }
+    if let Some(scroll) = self.diagnostics_scroll {
+      let max_scroll = render_diagnostics_popup(self.diagnostics_lines(), scroll, theme, area, buf);
+      self.diagnostics_max_scroll.set(max_scroll);
+    }
+    if self.show_help {
+      render_help_popup(&self.key_map, theme, area, buf);
+    }
+  }
+}
+
+impl App {
+  /// Number of subscriptions and their unread entries, leaving out muted feeds
+  fn subscription_counts(&self) -> (usize, usize) {
+    let subscriptions = self
+      .list
+      .iter()
+      .filter(|f| f.kind == FeedKind::Subscription);
+    let (mut feeds, mut unread) = (0, 0);
+    for feed in subscriptions {
+      feeds += 1;
+      if !feed.muted {
+        unread += visible_entries(feed)
+          .iter()
+          .filter(|&&i| !feed.entries[i].read)
+          .count();
+      }
+    }
+    (feeds, unread)
+  }
+
+  /// Unread entries and feeds across all subscriptions, and the time of the last refresh
+  fn render_status_bar(&self, area: Rect, buf: &mut Buffer) {
+    let theme = &self.theme;
+    let (feeds, unread) = self.subscription_counts();
+    let refreshed = match self.last_fetched {
+      Some(date) => format!("refreshed {}", time_ago(date, Utc::now())),
+      None => "never refreshed".to_string(),
+    };
+    let mut line = Line::from(vec![
+      format!(" {}", unread).fg(theme.title),
+      " unread · ".fg(theme.muted),
+      feeds.to_string().fg(theme.title),
+      format!(" feeds · {}", refreshed).fg(theme.muted),
+    ]);
+    if let Some((notice, given_at)) = &self.notice {
+      if given_at.elapsed() < NOTICE_DURATION {
+        line.push_span(" · ".fg(theme.muted));
+        line.push_span(notice.clone().fg(theme.accent));
+      }
+    }
+    line.render(area, buf);
+  }
+
+  /// Columns the tag sidebar needs for its longest row
+  fn tags_width(&self) -> u16 {
+    let widest = self
+      .tags()
+      .iter()
+      .map(|(tag, unread)| tag.width() + unread.to_string().len() + 1)
+      .chain([ALL_TAGS.len()])
+      .max()
+      .unwrap_or_default();
+    // Borders and a space on each side
+    widest as u16 + 4
+  }
+
+  /// The tag sidebar, a row listing every feed followed by each tag and its unread
+  /// entries
+  fn render_tags(&self, area: Rect, buf: &mut Buffer) {
+    let theme = &self.theme;
+    let block = self.list_block().title(" Tags ".fg(theme.label));
+    let inner = block.inner(area);
+    let row_width = inner.width as usize;
+    let tags = self.tags();
+    let mut rows = vec![ListItem::new(format!(" {}", ALL_TAGS))];
+    for (tag, unread) in &tags {
+      let count = format!("{} ", unread);
+      let tag = truncate_to_width(tag, row_width.saturating_sub(count.len() + 2));
+      let padding = row_width.saturating_sub(tag.width() + 1 + count.len());
+      rows.push(ListItem::new(Line::from(vec![
+        Span::from(format!(" {}", tag)),
+        Span::from(" ".repeat(padding)),
+        count.fg(theme.title),
+      ])));
+    }
+    let highlight_style = match self.active_list {
+      ActiveList::Tags => Style::default()
+        .bg(theme.highlight_bg)
+        .fg(theme.highlight_fg),
+      _ => Style::default().fg(theme.highlight_bg),
+    };
+    let mut state = ListState::default().with_selected(Some(self.selected_tag_row()));
+    StatefulWidget::render(
+      List::new(rows)
+        .block(block)
+        .highlight_style(highlight_style),
+      area,
+      buf,
+      &mut state,
+    );
+    self.tags_list_area.set((inner, state.offset()));
+    render_list_scrollbar(tags.len() + 1, state.offset(), theme, area, inner, buf);
+  }
+
+  /// Block around the tags, feeds and entries lists
+  fn list_block(&self) -> Block<'static> {
+    let borders = if self.show_borders {
+      Borders::ALL
+    } else {
+      Borders::NONE
+    };
+    Block::default()
+      .borders(borders)
+      .border_style(Style::new().fg(self.theme.border))
+      .border_set(border::PLAIN)
+  }
+
+  fn render_feeds(&self, area: Rect, buf: &mut Buffer) {
+    let count_width = self
+      .list
+      .iter()
+      .map(|f| feed_count(f, &self.config.read_count).chars().count())
+      .max()
+      .unwrap_or(0);
+    let theme = &self.theme;
+    let mut left_block = self.list_block();
+    let row_width = left_block.inner(area).width as usize;
+    let rows = self.listed_feed_rows();
+    let feeds = rows
+      .iter()
+      .map(|row| match row {
+        FeedRow::Header(tag) => ListItem::new(format!(" {}", tag).fg(self.theme.label).bold()),
+        FeedRow::Feed(i) => feed_row(
+          &self.list[*i],
+          self.fetch_errors.contains_key(&self.list[*i].url),
+          self
+            .feed_health
+            .get(&self.list[*i].url)
+            .is_some_and(|h| h.consecutive_failures >= FAILING_FEED_FAILURES),
+          &self.theme,
+          row_width,
+          count_width,
+          &self.config.read_count,
+        ),
+      })
+      .collect::<List>();
+    let selected = rows
+      .iter()
+      .position(|row| *row == FeedRow::Feed(self.index));
+    let feeds_len = rows
+      .iter()
+      .filter(|row| matches!(row, FeedRow::Feed(_)))
+      .count();
+
+    left_block = left_block
+      .title(" Feeds ".fg(theme.label))
+      .title(format!(" {} ", feeds_len).fg(theme.title));
+
+    // How the selected feed fared when it was last fetched
+    let selected_feed = self
+      .list
+      .get(self.index)
+      .filter(|f| f.kind == FeedKind::Subscription);
+    if let Some(feed) = selected_feed {
+      let status = match (
+        self.fetch_errors.get(&feed.url),
+        self.fetched_at.get(&feed.url),
+      ) {
+        (Some(error), _) => {
+          let error = error.lines().next().unwrap_or_default();
+          Some((format!("Failed: {}", error), theme.error))
+        }
+        (None, Some(&date)) => Some((
+          format!("Fetched {}", time_ago(date, Utc::now())),
+          theme.muted,
+        )),
+        (None, None) => None,
+      };
+      if let Some((status, color)) = status {
+        let status = truncate_to_width(&status, row_width.saturating_sub(4));
+        left_block = left_block.title_bottom(format!(" {} ", status).fg(color));
+      }
+    }
+
+    let inner = left_block.inner(area);
+
+    let feeds_highlight_style = match self.active_list {
+      ActiveList::Feeds => Style::default()
+        .bg(theme.highlight_bg)
+        .fg(theme.highlight_fg),
+      ActiveList::Tags | ActiveList::Entries => Style::default().fg(theme.highlight_bg),
+      _ => Style::default(),
+    };
+
+    let mut state = self.state.to_owned().with_selected(selected);
+    StatefulWidget::render(
+      feeds
+        .block(left_block)
+        .highlight_style(feeds_highlight_style),
+      area,
+      buf,
+      &mut state,
+    );
+    self.feeds_list_area.set((inner, state.offset()));
+    render_list_scrollbar(rows.len(), state.offset(), theme, area, inner, buf);
+  }
+
+  fn render_entries(&self, area: Rect, buf: &mut Buffer) {
+    let theme = &self.theme;
+    let entries = if let Some(feed) = self.list.get(self.index) {
+      let visible = self.listed_entries(self.index);
+      // Show entry numbers in a gutter while one is being typed
+      let gutter_width = visible.len().to_string().len();
+      let glyphs = &self.config.glyphs;
+      let glyph_width = [&glyphs.unread, &glyphs.read, &glyphs.starred]
+        .iter()
+        .map(|g| g.width())
+        .max()
+        .unwrap_or(0);
+      let columns = &self.config.entry_columns;
+      let now = Utc::now();
+      let sources: HashMap<&str, &str> = self
+        .list
+        .iter()
+        .filter(|f| f.kind == FeedKind::Subscription)
+        .map(|f| (f.url.as_str(), f.title.as_str()))
+        .collect();
+      let glyph = |e: &FeedEntry| match (e.starred, e.read) {
+        (true, _) => glyphs.starred.as_str(),
+        (false, false) => glyphs.unread.as_str(),
+        (false, true) => glyphs.read.as_str(),
+      };
+      // Text of every column but the title, which takes the space left
+      let cells: Vec<Vec<String>> = visible
+        .iter()
+        .map(|&i| {
+          let e = &feed.entries[i];
+          columns
+            .iter()
+            .map(|column| match column {
+              EntryColumn::Glyph => glyph(e).to_string(),
+              EntryColumn::Date => entry_date(e, &self.config.date_format, now),
+              EntryColumn::Source => truncate_to_width(
+                sources
+                  .get(e.feed_url.as_str())
+                  .unwrap_or(&e.feed_url.as_str()),
+                ENTRY_COLUMN_MAX_WIDTH,
+              ),
+              EntryColumn::Author => truncate_to_width(
+                e.author.as_deref().unwrap_or_default(),
+                ENTRY_COLUMN_MAX_WIDTH,
+              ),
+              EntryColumn::Title => String::new(),
+            })
+            .collect()
+        })
+        .collect();
+      // Columns that are blank in every row, like dates of undated entries, are left out
+      let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(c, column)| match column {
+          EntryColumn::Glyph => glyph_width,
+          _ => cells.iter().map(|row| row[c].width()).max().unwrap_or(0),
+        })
+        .collect();
+      let gutter = match self.pending_number {
+        Some(_) => gutter_width + 1,
+        None => 0,
+      };
+      let fixed_width: usize = 1
+        + gutter
+        + widths
+          .iter()
+          .filter(|&&w| w > 0)
+          .map(|w| w + 1)
+          .sum::<usize>();
+      let title_width =
+        (self.list_block().inner(area).width as usize).saturating_sub(fixed_width + 1);
+      visible
+        .iter()
+        .map(|&i| &feed.entries[i])
+        .zip(&cells)
+        .enumerate()
+        .map(|(i, (e, cells))| {
+          let mut line = Line::from(match self.pending_number {
+            Some(_) => format!(" {:>gutter_width$} ", i + 1),
+            None => " ".to_string(),
+          });
+          for (c, column) in columns.iter().enumerate() {
+            let last = c + 1 == columns.len();
+            if *column == EntryColumn::Title {
+              let mut width = title_width;
+              if !self.new_entries.is_empty() && self.new_entries.contains(&entry_key(e)) {
+                line.push_span("NEW ".fg(theme.accent).bold());
+                width = width.saturating_sub(4);
+              }
+              line.push_span(match last {
+                true => e.title.clone(),
+                false => pad_to_width(&truncate_to_width(&e.title, width), width) + " ",
+              });
+            } else if widths[c] > 0 {
+              let cell = match column {
+                EntryColumn::Date => {
+                  format!("{}{}", " ".repeat(widths[c] - cells[c].width()), cells[c])
+                }
+                _ => pad_to_width(&cells[c], widths[c]),
+              };
+              line.push_span(if last { cell } else { cell + " " });
+            }
+          }
+          ListItem::new(line).fg(if e.read { theme.read } else { theme.unread })
+        })
+        .collect::<Vec<_>>()
+    } else {
+      vec![]
+    };
+
+    let mut right_block = self
+      .list_block()
+      .title(" Entries ".fg(theme.label))
+      .title(format!(" {} ", entries.len()).fg(theme.title));
+    if self.entry_sort != EntrySort::default() {
+      right_block = right_block.title(format!(" {} ", self.entry_sort.label()).fg(theme.muted));
+    }
+    if let Some((digits, _)) = &self.pending_number {
+      right_block = right_block.title(format!(" Go to: {} ", digits).fg(theme.accent));
+    }
+    let inner = right_block.inner(area);
+
+    let rows = entries.len();
+    let secondary_list = List::new(entries)
+      .block(right_block.clone())
+      .highlight_style(Style::default().fg(theme.highlight_bg).bold());
+
+    let entries_highlight_style = match self.active_list {
+      ActiveList::Entries => Style::default()
+        .bg(theme.highlight_bg)
+        .fg(theme.highlight_fg)
+        .bold(),
+      ActiveList::Feeds => Style::default(),
+      _ => Style::default(),
+    };
+
+    let mut state = self.entries_state.to_owned();
+    StatefulWidget::render(
+      secondary_list
+        .block(right_block)
+        .highlight_style(entries_highlight_style),
+      area,
+      buf,
+      &mut state,
+    );
+    self.entries_list_area.set((inner, state.offset()));
+    render_list_scrollbar(rows, state.offset(), theme, area, inner, buf);
+  }
+}
+
+/// Draws a scrollbar along the right of a list of `rows` scrolled to `offset`, if it
+/// doesn't fit. `inner` is the part of `area` inside the list's block.
+fn render_list_scrollbar(
+  rows: usize,
+  offset: usize,
+  theme: &Theme,
+  area: Rect,
+  inner: Rect,
+  buf: &mut Buffer,
+) {
+  // Down the right border, or the last column of the list without borders
+  let track = Rect::new(area.x, inner.y, area.width, inner.height);
+  let visible = track.height as usize;
+  if rows <= visible {
+    return;
+  }
+  // The list can be scrolled to any offset that still fills the view
+  let mut state = ScrollbarState::new(rows - visible + 1).position(offset);
+  Scrollbar::new(ScrollbarOrientation::VerticalRight)
+    .begin_symbol(None)
+    .end_symbol(None)
+    .track_symbol(Some(border::PLAIN.vertical_right))
+    .style(Style::new().fg(theme.border))
+    .render(track, buf, &mut state);
+}
+
+/// Lines of the entry in view out of its laid out text and how much of it was read,
+/// with `height` rows inside the border of the app
+fn reading_progress(view: &EntryView, scroll: usize, height: u16) -> String {
+  // Without the blank rows above and below the text
+  let lines = view.line_count.saturating_sub(2);
+  let height = height.saturating_sub(2) as usize;
+  let first = scroll.min(lines.saturating_sub(height));
+  let last = (first + height).min(lines);
+  let percent = (last * 100).checked_div(lines).unwrap_or(100);
+  format!(" Lines {}–{} / {} · {}% ", first + 1, last, lines, percent)
+}
+
+/// Row of a list with its rows drawn at `inner` and scrolled to `offset` that
+/// `position` is on
+fn clicked_row((inner, offset): (Rect, usize), position: layout::Position) -> Option<usize> {
+  inner
+    .contains(position)
+    .then(|| offset + (position.y - inner.y) as usize)
+}
+
+/// How many enclosures of each kind there are, like "3 images, 1 audio", or `None`
+/// without any
+fn media_summary(enclosures: &[Enclosure]) -> Option<String> {
+  let kinds = [
+    (EnclosureKind::Image, "image", "images"),
+    (EnclosureKind::Video, "video", "videos"),
+    (EnclosureKind::Audio, "audio", "audio"),
+    (EnclosureKind::Other, "file", "files"),
+  ];
+  let counts: Vec<String> = kinds
+    .iter()
+    .filter_map(
+      |&(kind, one, many)| match enclosures.iter().filter(|e| e.kind() == kind).count() {
+        0 => None,
+        1 => Some(format!("1 {}", one)),
+        count => Some(format!("{} {}", count, many)),
+      },
+    )
+    .collect();
+  (!counts.is_empty()).then(|| counts.join(", "))
+}
+
+/// Builds the metadata header and body of the entry view, with the URLs in the body
+/// and the line each is on
+fn build_entry_content(
+  feed: &Feed,
+  entry: &FeedEntry,
+  queries: &[&str],
+  footnote_links: bool,
+  focused_link: Option<usize>,
+  theme: &Theme,
+) -> (Vec<Line<'static>>, Vec<(String, usize)>) {
+  let mut entry_content = vec![
+    Line::from(format!("Title: {}", entry.title).fg(theme.heading)), // Entry title
+    Line::from(format!("Feed: {}", feed.title).fg(theme.accent)),    // Feed title
+    Line::from(
+      match entry.published_at {
+        Some(date) => format!(
+          "Published: {} ({})",
+          entry.published.as_deref().unwrap_or_default(),
+          time_ago(date, Utc::now())
+        ),
+        None => format!(
+          "Published: {}",
+          entry.published.as_deref().unwrap_or("Unknown")
+        ),
+      }
+      .fg(theme.title),
+    ), // Publication date
+  ];
+
+  if let Some(author) = &entry.author {
+    entry_content.push(Line::from(format!("Author: {}", author).fg(theme.label)));
+  }
+
+  // Query feeds the entry is also listed in
+  if !queries.is_empty() {
+    entry_content.push(Line::from(
+      format!("Matches: {}", queries.join(", ")).fg(theme.accent),
+    ));
+  }
+
+  let (body, link_line) = render_links(
+    entry.plain_text.as_deref().unwrap_or_default(),
+    &entry.links,
+    footnote_links,
+  );
+  if let Some(link_line) = link_line {
+    entry_content.push(Line::from(format!("Link: {}", link_line).fg(theme.link)));
+  }
+
+  if let Some(summary) = media_summary(&entry.enclosures) {
+    entry_content.push(Line::from(format!("Media: {}", summary).fg(theme.link)));
+    for enclosure in &entry.enclosures {
+      let line = match &enclosure.mime_type {
+        Some(mime_type) => format!("  {} ({})", enclosure.url, mime_type),
+        None => format!("  {}", enclosure.url),
+      };
+      entry_content.push(Line::from(line.fg(theme.link)));
+    }
+  }
+
+  entry_content.push(Line::from("")); // Add a blank line for separation
+
+  // Combine metadata and text content
+  let mut links = Vec::new();
+  if entry.plain_text.is_some() {
+    let link_style = Style::new().fg(theme.link).underlined();
+    let focused_style = Style::new().fg(theme.highlight_fg).bg(theme.highlight_bg);
+    for line in body.lines() {
+      let mut spans = Vec::new();
+      let mut last = 0;
+      for (start, end) in find_urls(line) {
+        spans.push(Span::raw(line[last..start].to_string()));
+        let style = if focused_link == Some(links.len()) {
+          focused_style
+        } else {
+          link_style
+        };
+        spans.push(Span::styled(line[start..end].to_string(), style));
+        links.push((line[start..end].to_string(), entry_content.len()));
+        last = end;
+      }
+      spans.push(Span::raw(line[last..].to_string()));
+      entry_content.push(Line::from(spans));
+    }
+  } else {
+    entry_content.push(Line::from("Loading…".fg(theme.muted)));
+  }
+  (entry_content, links)
+}
+
+/// Byte ranges of the http(s) URLs in a line of text, leaving out punctuation that
+/// ends the sentence around them
+fn find_urls(line: &str) -> Vec<(usize, usize)> {
+  static URL: OnceLock<Regex> = OnceLock::new();
+  let url = URL.get_or_init(|| Regex::new(r#"https?://[^\s<>()\[\]"']+"#).unwrap());
+  url
+    .find_iter(line)
+    .filter_map(|m| {
+      let trimmed = m.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?']);
+      (!trimmed.ends_with("://")).then(|| (m.start(), m.start() + trimmed.len()))
+    })
+    .collect()
+}
+
+/// Lays out the links of an entry either inline or as numbered footnotes.
+///
+/// html2text renders body links as `[text][n]` with a trailing `[n]: url` list. Inline
+/// mode folds those back into the text, footnote mode keeps them and numbers the feed
+/// provided links after them. Returns the body and the text for the "Link:" line.
+fn render_links(plain_text: &str, links: &[String], footnotes: bool) -> (String, Option<String>) {
+  static REFERENCE: OnceLock<Regex> = OnceLock::new();
+  static FOOTNOTE: OnceLock<Regex> = OnceLock::new();
+  let reference = REFERENCE.get_or_init(|| Regex::new(r"\[([^\]]*)\]\[(\d+)\]").unwrap());
+  let footnote = FOOTNOTE.get_or_init(|| Regex::new(r"^\[(\d+)\]: (.+)$").unwrap());
+
+  // The footnote list is the block of `[n]: url` lines at the end of the text
+  let lines: Vec<&str> = plain_text.lines().collect();
+  let body_end = lines
+    .iter()
+    .rposition(|l| !l.is_empty() && !footnote.is_match(l))
+    .map_or(0, |i| i + 1);
+  let urls: Vec<(&str, &str)> = lines[body_end..]
+    .iter()
+    .filter_map(|l| footnote.captures(l))
+    .map(|c| (c.get(1).unwrap().as_str(), c.get(2).unwrap().as_str()))
+    .collect();
+  let body = lines[..body_end].join("\n");
+
+  if links.is_empty() && urls.is_empty() {
+    return (body, None);
+  }
+  if footnotes {
+    let first = urls.len() + 1;
+    let markers: Vec<String> = (first..first + links.len())
+      .map(|n| format!("[{}]", n))
+      .collect();
+    let mut text = body;
+    text.push_str("\n\nReferences:");
+    for (n, url) in &urls {
+      text.push_str(&format!("\n[{}] {}", n, url));
+    }
+    for (n, link) in (first..).zip(links) {
+      text.push_str(&format!("\n[{}] {}", n, link));
+    }
+    let link_line = (!markers.is_empty()).then(|| markers.join(", "));
+    (text, link_line)
+  } else {
+    let text = reference.replace_all(&body, |c: &regex::Captures| {
+      let url = urls.iter().find(|(n, _)| *n == &c[2]).map(|(_, url)| *url);
+      match url {
+        Some(url) => format!("{} <{}>", &c[1], url),
+        None => c[0].to_string(),
+      }
+    });
+    let link_line = (!links.is_empty()).then(|| links.join(", "));
+    (text.into_owned(), link_line)
+  }
+}
+
+/// Formats the time elapsed since `date` as e.g. "5m ago" or "3d ago"
+fn time_ago(date: DateTime<Utc>, now: DateTime<Utc>) -> String {
+  match relative_date(date, now) {
+    Some(elapsed) => format!("{} ago", elapsed),
+    None => "just now".to_string(),
+  }
+}
+
+/// Time since `date` in its largest unit, like "2h" or "3w", `None` under a minute
+fn relative_date(date: DateTime<Utc>, now: DateTime<Utc>) -> Option<String> {
+  let elapsed = now.signed_duration_since(date);
+  if elapsed.num_seconds() < 60 {
+    None
+  } else if elapsed.num_minutes() < 60 {
+    Some(format!("{}m", elapsed.num_minutes()))
+  } else if elapsed.num_hours() < 24 {
+    Some(format!("{}h", elapsed.num_hours()))
+  } else if elapsed.num_days() < 7 {
+    Some(format!("{}d", elapsed.num_days()))
+  } else if elapsed.num_days() < 365 {
+    Some(format!("{}w", elapsed.num_weeks()))
+  } else {
+    Some(format!("{}y", elapsed.num_days() / 365))
+  }
+}
+
+/// Date shown in the entries list, blank when the entry has no parseable date
+fn entry_date(entry: &FeedEntry, format: &DateFormat, now: DateTime<Utc>) -> String {
+  match (entry.published_at, format) {
+    (None, _) => String::new(),
+    (Some(date), DateFormat::Absolute) => date.format("%d %b").to_string(),
+    (Some(date), DateFormat::Relative) => {
+      relative_date(date, now).unwrap_or_else(|| "now".to_string())
+    }
+  }
+}
+
+/// Indices of the entries shown for a feed, hiding ones older than its
+/// `display_max_age_days`. Entries without a parseable date are always shown.
+fn visible_entries(feed: &Feed) -> Vec<usize> {
+  let cutoff = feed
+    .display_max_age_days
+    .map(|days| Utc::now() - chrono::Duration::days(days as i64));
+  feed
+    .entries
+    .iter()
+    .enumerate()
+    .filter(|(_, e)| match (cutoff, e.published_at) {
+      (Some(cutoff), Some(published)) => published >= cutoff,
+      _ => true,
+    })
+    .map(|(i, _)| i)
+    .collect()
+}
+
+/// Opens the cache for a command, exiting with the error if it's locked, corrupt or
+/// can't be written instead of panicking
+fn open_cache() -> FeedCache {
+  FeedCache::new(&config::cache_file()).unwrap_or_else(|e| {
+    eprintln!("Failed to open the cache database: {}", e);
+    std::process::exit(1);
+  })
+}
+
+/// Fetches every feed into the cache without taking over the terminal, e.g. from cron.
+/// Returns whether all feeds were fetched.
+async fn refresh_headless(config: Config) -> bool {
+  // Another process may hold the database, which a cron run shouldn't panic over
+  let mut cache = match FeedCache::new(&config::cache_file()) {
+    Ok(cache) => cache,
+    Err(e) => {
+      eprintln!("Failed to open the cache database: {}", e);
+      return false;
+    }
+  };
+  let client = match FeedClient::new(&config.settings) {
+    Ok(client) => client,
+    Err(e) => {
+      eprintln!("{}", e);
+      return false;
+    }
+  };
+  // Plain text is wrapped when parsed, so match the terminal if there is one
+  let area_width = crossterm::terminal::size().map_or(80, |(width, _)| width as usize);
+  let total = config.feeds.len();
+  let (tx, mut rx) = mpsc::unbounded_channel();
+  tokio::spawn(feeds::fetch_feed_with_progress(
+    client,
+    config.feeds.into_iter().enumerate().collect(),
+    cache.load_validators().unwrap_or_default(),
+    area_width,
+    config.settings.max_parsed_entries,
+    config.settings.fetch_concurrency,
+    tx,
+  ));
+  let mut failed = 0;
+  let mut merged = false;
+  while let Some(update) = rx.recv().await {
+    match update {
+      // Only the interface subscribes to feeds
+      FeedUpdate::FetchingFeed(_) | FeedUpdate::Validated { .. } | FeedUpdate::BulkValidated(_) => {
+      }
+      FeedUpdate::NotModified { feed, .. } => {
+        if let Err(e) = cache.record_not_modified(&feed.link) {
+          eprintln!("{}: {}", feed.link, e);
+        }
+      }
+      FeedUpdate::UpdateFeed { position, feed } => {
+        let feed = Feed { position, ..feed };
+        if let Err(e) = cache.save_all_feeds(std::slice::from_ref(&feed)) {
+          eprintln!("{}: {}", feed.title, e);
+          failed += 1;
+        }
+      }
+      FeedUpdate::FeedMoved { from, to, .. } => match record_feed_move(&cache, &from, &to) {
+        Ok(duplicate) => {
+          println!("{} moved to {}", from, to);
+          merged |= duplicate;
+        }
+        Err(e) => eprintln!("{}: {}", from, e),
+      },
+      FeedUpdate::FeedError { url, name, error } => {
+        eprintln!("{}: {}", name, error);
+        if let Err(e) = cache.record_failure(&url) {
+          eprintln!("{}: {}", url, e);
+        }
+        failed += 1;
+      }
+      FeedUpdate::FetchComplete => break,
+    }
+  }
+  // Feeds were saved at their position before the merged ones left urls.toml
+  if merged {
+    let result = config::parse_feed_urls().and_then(|feeds| {
+      let positions: Vec<(&str, usize)> = feeds
+        .iter()
+        .enumerate()
+        .map(|(position, feed)| (feed.link.as_str(), position))
+        .collect();
+      cache
+        .update_positions(&positions)
+        .map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+      eprintln!("Failed to update feed positions: {}", e);
+    }
+  }
+  let (retention_days, max_entries) = (
+    config.settings.retention_days,
+    config.settings.max_entries_per_feed,
+  );
+  if retention_days.is_some() || max_entries.is_some() {
+    if let Err(e) = cache.prune_entries(retention_days, max_entries) {
+      eprintln!("Failed to prune the cache: {}", e);
+    }
+  }
+  // Counted in the database, the entries don't need to be loaded for the total
+  let unread = config::parse_feed_urls()
+    .ok()
+    .zip(cache.unread_counts().ok())
+    .map(|(feeds, counts)| {
+      feeds
+        .iter()
+        .filter(|f| !f.muted)
+        .filter_map(|f| counts.get(&f.link))
+        .map(|(unread, _)| unread)
+        .sum::<usize>()
+    });
+  match unread {
+    Some(unread) => println!(
+      "Refreshed {} feeds, {} failed, {} unread",
+      total - failed,
+      failed,
+      unread
+    ),
+    None => println!("Refreshed {} feeds, {} failed", total - failed, failed),
+  }
+  failed == 0
+}
+
+/// Stores a permanently redirected feed under its new URL in urls.toml and the cache.
+/// Returns whether the new URL was already subscribed, the old feed is removed then.
+fn record_feed_move(cache: &FeedCache, from: &str, to: &str) -> Result<bool, String> {
+  let duplicate =
+    config::move_feed(from, to).map_err(|e| format!("Failed to update urls.toml: {}", e))?;
+  cache
+    .move_feed(from, to)
+    .map_err(|e| format!("Cache operation failed: {}", e))?;
+  Ok(duplicate)
+}
+
+/// Formats a byte count with a binary unit, e.g. "1.5 MiB"
+fn format_size(bytes: u64) -> String {
+  const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+  let mut size = bytes as f64;
+  let mut unit = 0;
+  while size >= 1024.0 && unit + 1 < UNITS.len() {
+    size /= 1024.0;
+    unit += 1;
+  }
+  match unit {
+    0 => format!("{} B", bytes),
+    _ => format!("{:.1} {}", size, UNITS[unit]),
+  }
+}
+
+/// Reads the selection `App::save_selection` stored
+fn load_last_selection(cache: &FeedCache) -> rusqlite::Result<Option<LastSelection>> {
+  let Some(feed) = cache.load_state("feed")? else {
+    return Ok(None);
+  };
+  let entry = match cache.load_state("entry_title")? {
+    Some(title) => Some((title, cache.load_state("entry_published")?)),
+    None => None,
+  };
+  Ok(Some(LastSelection { feed, entry }))
+}
+
+/// Identifies a feed across refreshes, query and search feeds have no URL
+fn selection_key(feed: &Feed) -> String {
+  match feed.kind {
+    FeedKind::Subscription => feed.url.clone(),
+    _ => feed.title.clone(),
+  }
+}
+
+/// Identifies an entry in the cache by feed URL, title and publication date
+fn entry_key(entry: &FeedEntry) -> (String, String, Option<String>) {
+  (
+    entry.feed_url.clone(),
+    entry.title.clone(),
+    entry.published.clone(),
+  )
+}
+
+/// Tag a feed is grouped under with `group_by_tag`
+fn group_tag(feed: &Feed) -> Option<&str> {
+  feed.tags.as_ref()?.first().map(String::as_str)
+}
+
+/// Feeds of the feeds list rows, skipping the headers
+fn feed_indices(rows: Vec<FeedRow>) -> Vec<usize> {
+  rows
+    .into_iter()
+    .filter_map(|row| match row {
+      FeedRow::Feed(i) => Some(i),
+      FeedRow::Header(_) => None,
+    })
+    .collect()
+}
+
+/// Whether a feed contributes unread entries to the count column
+fn has_unread(feed: &Feed) -> bool {
+  !feed.muted && visible_entries(feed).iter().any(|&i| !feed.entries[i].read)
+}
+
+/// Formats the count column of a feed, depending on how fully read feeds should be shown
+fn feed_count(feed: &Feed, read_count: &ReadCount) -> String {
+  let visible = visible_entries(feed);
+  let unread = if feed.muted {
+    0
+  } else {
+    visible.iter().filter(|&&i| !feed.entries[i].read).count()
+  };
+  // Entries read since the query feed was built are on their way out
+  let total = match &feed.kind {
+    FeedKind::Query(query) if query.unread_only => unread,
+    _ => visible.len(),
+  };
+  match read_count {
+    _ if unread > 0 => format!("{}/{}", unread, total),
+    ReadCount::Full => format!("{}/{}", unread, total),
+    ReadCount::Total => total.to_string(),
+    ReadCount::Hidden => String::new(),
+  }
+}
+
+/// Builds a feed list row with the title on the left and the count right-aligned.
+/// Feeds whose last fetch `failed` are drawn in the error color, and marked when
+/// they're `failing` repeatedly.
+fn feed_row<'a>(
+  feed: &'a Feed,
+  failed: bool,
+  failing: bool,
+  theme: &Theme,
+  width: usize,
+  count_width: usize,
+  read_count: &ReadCount,
+) -> ListItem<'a> {
+  let title = if failing {
+    format!(" ✗ {}", feed.title).fg(theme.error)
+  } else if failed {
+    format!(" {}", feed.title).fg(theme.error)
+  } else if feed.muted {
+    format!(" {}", feed.title).fg(theme.muted)
+  } else {
+    Span::from(format!(" {}", feed.title))
+  };
+  let count = format!(
+    "{:>width$} ",
+    feed_count(feed, read_count),
+    width = count_width
+  );
+  let padding = width.saturating_sub(title.width() + count.chars().count());
+  ListItem::new(Line::from(vec![
+    title,
+    Span::from(" ".repeat(padding)),
+    count.fg(theme.title),
+  ]))
+}
+
+/// Returns a rectangle of the given size percentage centered inside `area`
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+  let vertical = Layout::default()
+    .direction(Direction::Vertical)
+    .constraints([
+      Constraint::Percentage((100 - percent_y) / 2),
+      Constraint::Percentage(percent_y),
+      Constraint::Percentage((100 - percent_y) / 2),
+    ])
+    .split(area);
+  Layout::default()
+    .direction(Direction::Horizontal)
+    .constraints([
+      Constraint::Percentage((100 - percent_x) / 2),
+      Constraint::Percentage(percent_x),
+      Constraint::Percentage((100 - percent_x) / 2),
+    ])
+    .split(vertical[1])[1]
+}
+
+/// Marks the matches of `pattern` in the laid out entry, the current one like a selected
+/// row, and returns the row each match is on
+fn highlight_matches(
+  lines: &mut [Line<'static>],
+  pattern: &Regex,
+  current: Option<usize>,
+  text_width: u16,
+  wrap_lines: bool,
+  theme: &Theme,
+) -> Vec<usize> {
+  let mut matches = Vec::new();
+  let mut rows = 0;
+  for line in lines.iter_mut() {
+    let text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+    let mut ranges = Vec::new();
+    for found in pattern.find_iter(&text) {
+      // Rows the text before the match wraps onto
+      let row = if wrap_lines {
+        Paragraph::new(&text[..found.start()])
+          .wrap(Wrap { trim: false })
+          .line_count(text_width)
+          .saturating_sub(1)
+      } else {
+        0
+      };
+      let style = if current == Some(matches.len()) {
+        Style::new().fg(theme.highlight_fg).bg(theme.highlight_bg)
+      } else {
+        Style::new().add_modifier(Modifier::REVERSED)
+      };
+      matches.push(rows + row);
+      ranges.push((found.range(), style));
+    }
+    rows += if wrap_lines {
+      Paragraph::new(line.clone())
+        .wrap(Wrap { trim: false })
+        .line_count(text_width)
+    } else {
+      1
+    };
+    if ranges.is_empty() {
+      continue;
+    }
+    // Split the spans where the matches start and end
+    let mut spans = Vec::with_capacity(line.spans.len() + ranges.len() * 2);
+    let mut start = 0;
+    for span in line.spans.drain(..) {
+      let end = start + span.content.len();
+      let mut at = start;
+      for (range, style) in ranges
+        .iter()
+        .filter(|(r, _)| r.start < end && r.end > start)
+      {
+        let (from, to) = (range.start.max(start), range.end.min(end));
+        if at < from {
+          spans.push(Span::styled(
+            span.content[at - start..from - start].to_string(),
+            span.style,
+          ));
+        }
+        spans.push(Span::styled(
+          span.content[from - start..to - start].to_string(),
+          span.style.patch(*style),
+        ));
+        at = to;
+      }
+      if at == start {
+        spans.push(span);
+      } else if at < end {
+        spans.push(Span::styled(
+          span.content[at - start..].to_string(),
+          span.style,
+        ));
+      }
+      start = end;
+    }
+    line.spans = spans;
+  }
+  matches
+}
+
+/// Key of `app_state` the layout chosen for a size bucket is kept under
+fn layout_state_key(bucket: SizeBucket) -> String {
+  format!("layout_{}", bucket.name())
+}
+
+/// Cuts `text` to at most `max_width` columns, ending it with an ellipsis when cut.
+/// Feed names are often not ASCII, so this goes by display width rather than bytes.
+fn truncate_to_width(text: &str, max_width: usize) -> String {
+  if text.width() <= max_width {
+    return text.to_string();
+  }
+  let mut truncated = String::new();
+  let mut width = 0;
+  for c in text.chars() {
+    width += c.width().unwrap_or(0);
+    if width > max_width.saturating_sub(1) {
+      break;
+    }
+    truncated.push(c);
+  }
+  truncated.push('…');
+  truncated
+}
+
+/// Pads `text` with spaces to `width` columns
+fn pad_to_width(text: &str, width: usize) -> String {
+  format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+}
+
+/// Shows the refresh progress in the bottom right corner, followed by a short summary
+fn render_loading_popup(loading_state: &LoadingState, theme: &Theme, area: Rect, buf: &mut Buffer) {
+  let errors = match loading_state.failed {
+    0 => String::new(),
+    1 => ", 1 error".to_string(),
+    failed => format!(", {} errors", failed),
+  };
+  let progress = format!(
+    "{}/{} fetched{}",
+    loading_state.fetched, loading_state.total, errors
+  );
+  let lines = if loading_state.is_loading {
+    let elapsed = loading_state.started.elapsed();
+    let spinner = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+    let name = loading_state.current.as_deref().unwrap_or("feeds");
+    let name = truncate_to_width(name, 26);
+    let fetching = format!(
+      "{} Fetching {} ({:.1}s)",
+      spinner,
+      name,
+      elapsed.as_secs_f32()
+    );
+    vec![fetching, progress]
+  } else {
+    match loading_state.finished {
+      Some(finished) if finished.elapsed() < LOADING_SUMMARY_DURATION => {
+        let elapsed = finished.duration_since(loading_state.started);
+        let summary = format!(
+          "{} loaded in {:.1}s",
+          loading_state.only.as_deref().unwrap_or("Feeds"),
+          elapsed.as_secs_f32()
+        );
+        vec![summary, progress]
+      }
+      _ => return,
+    }
+  };
+
+  let text_width = lines.iter().map(|l| l.width()).max().unwrap_or(0);
+  let width = (text_width as u16 + 4).min(area.width);
+  let height = lines.len() as u16 + 2;
+  let popup_area = Rect {
+    x: area.right().saturating_sub(width + 1),
+    y: area.bottom().saturating_sub(height + 2),
+    width,
+    height: height.min(area.height),
+  };
+  Clear.render(popup_area, buf);
+  Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+    .block(
+      Block::default()
+        .title(" Loading ".fg(theme.label))
+        .borders(Borders::ALL)
+        .border_style(Style::new().fg(theme.border))
+        .border_set(border::PLAIN)
+        .padding(Padding::horizontal(1)),
+    )
+    .render(popup_area, buf);
+}
+
+fn render_help_popup(key_map: &KeyMap, theme: &Theme, area: Rect, buf: &mut Buffer) {
+  let mut bindings: Vec<(String, &str)> = Action::ALL
+    .iter()
+    .map(|action| {
+      let keys = key_map
+        .keys(*action)
+        .iter()
+        .map(|k| k.to_string())
+        .collect::<Vec<_>>();
+      (keys.join(" / "), action.description())
+    })
+    .collect();
+  bindings.insert(5, ("0-9".to_string(), "Jump to entry by number"));
+  let key_width = bindings
+    .iter()
+    .map(|(k, _)| k.chars().count())
+    .max()
+    .unwrap_or(0);
+  let lines: Vec<Line> = bindings
+    .iter()
+    .map(|(key, action)| {
+      Line::from(vec![
+        format!(" {:>key_width$}  ", key).fg(theme.title).bold(),
+        action.to_string().into(),
+      ])
+    })
+    .collect();
+  // Two rows of padding and two of borders around the bindings
+  let height = (lines.len() as u16 + 4).min(area.height);
+  let popup_area = centered_rect(60, 100, area);
+  let popup_area = Rect {
+    y: popup_area.y + (popup_area.height - height) / 2,
+    height,
+    ..popup_area
+  };
+  Clear.render(popup_area, buf);
+  Paragraph::new(lines)
+    .block(
+      Block::default()
+        .title(" Help ".fg(theme.label))
+        .title_bottom(Line::from(" Close <?/Esc> ").right_aligned())
+        .borders(Borders::ALL)
+        .border_style(Style::new().fg(theme.border))
+        .border_set(border::PLAIN)
+        .padding(Padding::vertical(1)),
+    )
+    .render(popup_area, buf);
+}
+
+fn render_message_popup(title: &str, message: &str, theme: &Theme, area: Rect, buf: &mut Buffer) {
+  let block = Block::default()
+    .title(title.fg(theme.label))
+    .borders(Borders::ALL)
+    .border_style(Style::new().fg(theme.border))
+    .border_set(border::PLAIN)
+    .padding(Padding::uniform(1));
+  let paragraph = Paragraph::new(message)
+    .alignment(Alignment::Center)
+    .wrap(Wrap { trim: true });
+  let popup_area = centered_rect(50, 20, area);
+  // Grow to fit long messages, borders and padding take two columns and rows on each side
+  let text_height = paragraph.line_count(popup_area.width.saturating_sub(4)) as u16;
+  let height = popup_area.height.max(text_height + 4).min(area.height);
+  let popup_area = Rect {
+    y: area.y + (area.height - height) / 2,
+    height,
+    ..popup_area
+  };
+  Clear.render(popup_area, buf);
+  paragraph.block(block).render(popup_area, buf);
+}
+
+/// Draws the search input over the bottom row of the lists
+fn render_search_line(input: &str, theme: &Theme, area: Rect, buf: &mut Buffer) {
+  let line_area = Rect {
+    y: area.bottom().saturating_sub(1),
+    height: area.height.min(1),
+    ..area
+  };
+  Clear.render(line_area, buf);
+  Line::from(vec!["/".fg(theme.title).bold(), input.into(), "█".into()]).render(line_area, buf);
+}
+
+fn render_find_line(find: &EntryFind, theme: &Theme, area: Rect, buf: &mut Buffer) {
+  let line_area = Rect {
+    y: area.bottom().saturating_sub(1),
+    height: area.height.min(1),
+    ..area
+  };
+  Clear.render(line_area, buf);
+  let case = if find.case_sensitive {
+    " Match case "
+  } else {
+    " Ignore case "
+  };
+  Line::from(vec![
+    "/".fg(theme.title).bold(),
+    find.query.as_str().into(),
+    "█".into(),
+    case.fg(theme.muted),
+    "<Tab>".fg(theme.muted).bold(),
+  ])
+  .render(line_area, buf);
+}
+
+fn render_bulk_input_popup(input: &str, theme: &Theme, area: Rect, buf: &mut Buffer) {
+  let popup_area = centered_rect(60, 50, area);
+  Clear.render(popup_area, buf);
+  let block = Block::default()
+    .title(" Subscribe to URLs ".fg(theme.label))
+    .title_bottom(Line::from(vec![
+      " Submit ".into(),
+      "<C-d> ".bold(),
+      " Cancel ".into(),
+      "<Esc> ".bold(),
+    ]))
+    .borders(Borders::ALL)
+    .border_style(Style::new().fg(theme.border))
+    .border_set(border::PLAIN);
+  let text = if input.is_empty() {
+    Text::from("Paste or type feed URLs, one per line".fg(theme.muted))
+  } else {
+    Text::from(input)
+  };
+  Paragraph::new(text)
+    .block(block)
+    .wrap(Wrap { trim: false })
+    .render(popup_area, buf);
+}
+
+fn render_add_feed_popup(dialog: &AddFeedDialog, theme: &Theme, area: Rect, buf: &mut Buffer) {
+  let label_width = AddFeedDialog::LABELS
+    .iter()
+    .map(|l| l.len())
+    .max()
+    .unwrap_or(0);
+  let mut lines: Vec<Line> = dialog
+    .fields
+    .iter()
+    .zip(AddFeedDialog::LABELS)
+    .enumerate()
+    .map(|(i, (value, label))| {
+      let cursor = if i == dialog.focus { "█" } else { "" };
+      Line::from(vec![
+        format!("{:>label_width$}: ", label).fg(theme.title).bold(),
+        value.as_str().into(),
+        cursor.into(),
+      ])
+    })
+    .collect();
+  if dialog.fields[2].is_empty() {
+    lines[2].push_span("comma separated".fg(theme.muted));
+  }
+  if dialog.checking {
+    lines.push(Line::from(""));
+    lines.push(Line::from("Fetching the feed…".fg(theme.muted)));
+  } else if let Some(error) = &dialog.error {
+    lines.push(Line::from(""));
+    lines.push(Line::from(error.as_str().fg(theme.error)));
+  }
+  // One row of padding and one of borders on both sides
+  let height = (lines.len() as u16 + 4).min(area.height);
+  let popup_area = centered_rect(60, 100, area);
+  let popup_area = Rect {
+    y: popup_area.y + (popup_area.height - height) / 2,
+    height,
+    ..popup_area
+  };
+  Clear.render(popup_area, buf);
+  let block = Block::default()
+    .title(" Add feed ".fg(theme.label))
+    .title_bottom(Line::from(vec![
+      " Add ".into(),
+      "<Enter> ".bold(),
+      " Next field ".into(),
+      "<Tab> ".bold(),
+      " Cancel ".into(),
+      "<Esc> ".bold(),
+    ]))
+    .borders(Borders::ALL)
+    .border_style(Style::new().fg(theme.border))
+    .border_set(border::PLAIN)
+    .padding(Padding::uniform(1));
+  Paragraph::new(lines)
+    .block(block)
+    .wrap(Wrap { trim: false })
+    .render(popup_area, buf);
+}
+
+/// Draws the diagnostics popup scrolled down by `scroll` lines, at most as far as the
+/// returned number of lines
+fn render_diagnostics_popup(
+  lines: Vec<Line>,
+  scroll: usize,
+  theme: &Theme,
+  area: Rect,
+  buf: &mut Buffer,
+) -> usize {
+  let popup_area = centered_rect(80, 70, area);
+  Clear.render(popup_area, buf);
+  let block = Block::default()
+    .title(" Feed health ".fg(theme.label))
+    .title_bottom(Line::from(" Close <Esc> ").right_aligned())
+    .borders(Borders::ALL)
+    .border_style(Style::new().fg(theme.border))
+    .border_set(border::PLAIN);
+  let max_scroll = lines
+    .len()
+    .saturating_sub(block.inner(popup_area).height as usize);
+  Paragraph::new(lines)
+    .block(block)
+    .scroll((scroll.min(max_scroll) as u16, 0))
+    .render(popup_area, buf);
+  max_scroll
+}
+
+fn render_bulk_checking_popup(urls: &[String], theme: &Theme, area: Rect, buf: &mut Buffer) {
+  let popup_area = centered_rect(60, 50, area);
+  Clear.render(popup_area, buf);
+  let block = Block::default()
+    .title(format!(" Subscribing to {} URLs ", urls.len()).fg(theme.label))
+    .title_bottom(Line::from(" Cancel <Esc> ").right_aligned())
+    .borders(Borders::ALL)
+    .border_style(Style::new().fg(theme.border))
+    .border_set(border::PLAIN);
+  let lines: Vec<Line> = urls
+    .iter()
+    .map(|url| Line::from(format!(" … {}", url).fg(theme.muted)))
+    .collect();
+  Paragraph::new(lines)
+    .block(block)
+    .wrap(Wrap { trim: false })
+    .render(popup_area, buf);
+}
+
+fn render_bulk_results_popup(
+  results: &[(String, Result<(), String>)],
+  theme: &Theme,
+  area: Rect,
+  buf: &mut Buffer,
+) {
+  let popup_area = centered_rect(60, 50, area);
+  Clear.render(popup_area, buf);
+  let subscribed = results.iter().filter(|(_, r)| r.is_ok()).count();
+  let block = Block::default()
+    .title(format!(" Subscribed {}/{} ", subscribed, results.len()).fg(theme.label))
+    .title_bottom(Line::from(" Close <any key> ").right_aligned())
+    .borders(Borders::ALL)
+    .border_style(Style::new().fg(theme.border))
+    .border_set(border::PLAIN);
+  let lines: Vec<Line> = results
+    .iter()
+    .map(|(url, result)| match result {
+      Ok(()) => Line::from(format!(" ✓ {}", url).fg(theme.label)),
+      Err(e) => Line::from(format!(" ✗ {}: {}", url, e).fg(theme.error)),
+    })
+    .collect();
+  Paragraph::new(lines)
+    .block(block)
+    .wrap(Wrap { trim: false })
+    .render(popup_area, buf);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use feeds::Validators;
+  use std::path::Path;
+
+  const LONG_NAME: &str = "日本のニュースフィード………長い名前のテスト";
+
+  #[test]
+  fn truncates_multibyte_names_by_width() {
+    let name = truncate_to_width(LONG_NAME, 25);
+    assert!(name.width() <= 25, "{}", name);
+    assert!(name.ends_with('…'));
+    assert!(LONG_NAME.starts_with(name.trim_end_matches('…')));
+  }
+
+  #[test]
+  fn leaves_room_for_the_ellipsis_before_a_wide_character() {
+    // The second character would end at column 4, past the ellipsis
+    assert_eq!(truncate_to_width("日本語", 4), "日…");
+    assert_eq!(truncate_to_width("日本語", 6), "日本語");
+  }
+
+  #[test]
+  fn renders_the_loading_popup_for_a_multibyte_name() {
+    let loading_state = LoadingState {
+      is_loading: true,
+      current: Some(LONG_NAME.to_string()),
+      started: Instant::now(),
+      finished: None,
+      total: 3,
+      fetched: 1,
+      failed: 0,
+      only: None,
+    };
+    let area = Rect::new(0, 0, 80, 10);
+    let mut buf = Buffer::empty(area);
+    render_loading_popup(&loading_state, &Theme::DEFAULT, area, &mut buf);
+    // Wide characters take two cells, the second one is blank
+    let text: String = buf
+      .content()
+      .iter()
+      .map(|cell| cell.symbol())
+      .filter(|symbol| *symbol != " ")
+      .collect();
+    assert!(text.contains("Fetching日本のニュースフィード"), "{}", text);
+    assert!(text.contains("1/3fetched"));
+  }
+
+  fn entry(title: &str, day: u32) -> FeedEntry {
+    let published = format!("2024-01-{:02}T00:00:00Z", day);
+    FeedEntry {
+      title: title.to_string(),
+      published_at: published.parse().ok(),
+      published: Some(published),
+      author: None,
+      plain_text: Some(format!("Text of {}", title)),
+      links: Vec::new(),
+      enclosures: Vec::new(),
+      read: false,
+      starred: false,
+      feed_url: "http://example.com/feed.xml".to_string(),
+    }
+  }
+
+  fn feed(entries: Vec<FeedEntry>) -> Feed {
+    Feed {
+      url: "http://example.com/feed.xml".to_string(),
+      title: "Example".to_string(),
+      entries,
+      tags: None,
+      muted: false,
+      position: 0,
+      validators: Validators::default(),
+      display_max_age_days: None,
+      kind: FeedKind::Subscription,
+    }
+  }
+
+  fn app() -> App {
+    let config = UserConfig::default();
+    let key_map = config.key_map().unwrap();
+    let theme = config.theme.resolve().unwrap();
+    let cache = FeedCache::new(Path::new(":memory:")).unwrap();
+    let client = FeedClient::new(&config).unwrap();
+    App::new(80, config, key_map, theme, cache, client)
+  }
+
+  fn selected_title(app: &App) -> Option<&str> {
+    let i = app.selected_entry_index()?;
+    Some(app.list[app.index].entries[i].title.as_str())
+  }
+
+  #[test]
+  fn a_refresh_keeps_the_open_entry_when_new_ones_arrive_above_it() {
+    let mut app = app();
+    app.show_unread_only = true;
+    app.insert_feed(feed(vec![entry("Older", 1), entry("Newer", 2)]));
+    app.index = 1;
+    app.enter();
+    app.next();
+    assert_eq!(selected_title(&app), Some("Older"));
+    app.open_entry();
+
+    // Fetched feeds come in their own order, with the open entry read by now
+    let mut refreshed = feed(vec![
+      entry("Newest", 4),
+      entry("Older", 1),
+      entry("New", 3),
+      entry("Newer", 2),
+    ]);
+    refreshed.entries[1].read = true;
+    app.insert_feed(refreshed);
+
+    assert_eq!(selected_title(&app), Some("Older"));
+    assert_eq!(app.read_in_view, [1]);
+    // It stays listed among the unread entries until the feed is left
+    let listed: Vec<&str> = app
+      .listed_entries(app.index)
+      .into_iter()
+      .map(|i| app.list[app.index].entries[i].title.as_str())
+      .collect();
+    assert_eq!(listed, ["Newest", "New", "Newer", "Older"]);
   }
 }
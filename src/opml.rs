@@ -0,0 +1,196 @@
+use crate::config::{self, Feeds};
+use std::{fmt::Write, fs, path::Path};
+use tl::{NodeHandle, Parser, ParserOptions};
+
+/// Reads an OPML file and appends every feed not yet in urls.toml to it.
+/// Returns the number of (imported, already subscribed) feeds.
+pub fn import_opml(path: &Path) -> Result<(usize, usize), String> {
+  let xml = fs::read_to_string(path).map_err(|e| format!("Failed to read the file: {}", e))?;
+  let feeds = parse_opml(&xml)?;
+  let mut existing: Vec<String> = config::parse_feed_urls()?
+    .into_iter()
+    .map(|f| f.link)
+    .collect();
+  let (mut imported, mut skipped) = (0, 0);
+  for feed in feeds {
+    if existing.contains(&feed.link) {
+      skipped += 1;
+      continue;
+    }
+    config::append_feed(&feed).map_err(|e| format!("Failed to update urls.toml: {}", e))?;
+    existing.push(feed.link);
+    imported += 1;
+  }
+  Ok((imported, skipped))
+}
+
+/// Writes the feeds of urls.toml to an OPML 2.0 file, grouped by their first tag.
+/// Returns the number of feeds exported.
+pub fn export_opml(path: &Path) -> Result<usize, String> {
+  let feeds = config::parse_feed_urls()?;
+  fs::write(path, to_opml(&feeds)).map_err(|e| format!("Failed to write the file: {}", e))?;
+  Ok(feeds.len())
+}
+
+fn to_opml(feeds: &[Feeds]) -> String {
+  let mut groups: Vec<(Option<&str>, Vec<&Feeds>)> = Vec::new();
+  for feed in feeds {
+    let group = feed
+      .tags
+      .as_ref()
+      .and_then(|t| t.first())
+      .map(String::as_str);
+    match groups.iter_mut().find(|(g, _)| *g == group) {
+      Some((_, members)) => members.push(feed),
+      None => groups.push((group, vec![feed])),
+    }
+  }
+
+  let mut opml = String::from(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n  <head>\n    <title>shinbun subscriptions</title>\n  </head>\n  <body>\n",
+  );
+  for (group, members) in groups {
+    let indent = match group {
+      Some(group) => {
+        let group = escape(group);
+        let _ = writeln!(opml, "    <outline text=\"{}\" title=\"{}\">", group, group);
+        "      "
+      }
+      None => "    ",
+    };
+    for feed in members {
+      let text = escape(feed.name.as_deref().unwrap_or(&feed.link));
+      let _ = write!(
+        opml,
+        "{}<outline type=\"rss\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\"",
+        indent,
+        text,
+        text,
+        escape(&feed.link)
+      );
+      if let Some(tags) = feed.tags.as_ref().filter(|t| !t.is_empty()) {
+        let _ = write!(opml, " category=\"{}\"", escape(&tags.join(",")));
+      }
+      opml.push_str("/>\n");
+    }
+    if group.is_some() {
+      opml.push_str("    </outline>\n");
+    }
+  }
+  opml.push_str("  </body>\n</opml>\n");
+  opml
+}
+
+/// Collects the feeds of an OPML document. `category` attributes and the titles of
+/// the groups an outline is nested in become its tags.
+pub fn parse_opml(xml: &str) -> Result<Vec<Feeds>, String> {
+  let dom = tl::parse(xml, ParserOptions::default()).map_err(|e| format!("Invalid OPML: {}", e))?;
+  let parser = dom.parser();
+  let mut feeds = Vec::new();
+  for handle in dom.children() {
+    collect_outlines(*handle, parser, &[], &mut feeds);
+  }
+  if feeds.is_empty() {
+    return Err("No feeds found in the OPML file".to_string());
+  }
+  Ok(feeds)
+}
+
+fn collect_outlines(
+  handle: NodeHandle,
+  parser: &Parser,
+  groups: &[String],
+  feeds: &mut Vec<Feeds>,
+) {
+  let Some(tag) = handle.get(parser).and_then(|node| node.as_tag()) else {
+    return;
+  };
+  let is_outline = tag.name().as_utf8_str().eq_ignore_ascii_case("outline");
+  let attribute = |key: &'static str| {
+    tag
+      .attributes()
+      .get(key)
+      .flatten()
+      .map(|value| unescape(&value.as_utf8_str()))
+      .filter(|value| !value.is_empty())
+  };
+
+  let mut nested = groups.to_vec();
+  if is_outline {
+    let title = attribute("text").or_else(|| attribute("title"));
+    match attribute("xmlUrl") {
+      Some(link) => {
+        let mut tags = groups.to_vec();
+        // Categories are comma separated, each one may be a slash delimited path
+        for category in attribute("category")
+          .iter()
+          .flat_map(|c| c.split([',', '/']))
+        {
+          let category = category.trim();
+          if !category.is_empty() && !tags.iter().any(|t| t == category) {
+            tags.push(category.to_string());
+          }
+        }
+        feeds.push(Feeds {
+          // Exports list feeds without a name under their URL
+          name: title.filter(|title| *title != link),
+          link,
+          tags: (!tags.is_empty()).then_some(tags),
+          ..Default::default()
+        });
+      }
+      // An outline without a feed URL is a group of feeds
+      None => nested.extend(title),
+    }
+  }
+  for child in tag.children().top().iter() {
+    collect_outlines(*child, parser, &nested, feeds);
+  }
+}
+
+/// Resolves the predefined XML entities and numeric character references
+pub fn unescape(value: &str) -> String {
+  let mut result = String::with_capacity(value.len());
+  let mut rest = value;
+  while let Some(start) = rest.find('&') {
+    result.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let Some(end) = rest.find(';') else {
+      break;
+    };
+    let decoded = match &rest[1..end] {
+      "amp" => Some('&'),
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      entity => entity
+        .strip_prefix("#x")
+        .map(|hex| u32::from_str_radix(hex, 16))
+        .or_else(|| entity.strip_prefix('#').map(str::parse::<u32>))
+        .and_then(Result::ok)
+        .and_then(char::from_u32),
+    };
+    match decoded {
+      Some(c) => {
+        result.push(c);
+        rest = &rest[end + 1..];
+      }
+      None => {
+        result.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  result.push_str(rest);
+  result
+}
+
+/// Escapes text for use inside a double quoted XML attribute
+fn escape(value: &str) -> String {
+  value
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
@@ -0,0 +1,562 @@
+use crate::feeds::{Feed, FeedEntry, FeedKind, Validators};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::HashSet;
+
+/// A query feed's filter with the options of its `[[queries]]` table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+  pub filter: QueryFilter,
+  /// List an entry that several feeds syndicate once, from the first of them
+  pub dedupe: bool,
+  /// Only list entries not read yet
+  pub unread_only: bool,
+}
+
+impl Query {
+  /// Whether an entry of `feed` is listed in the query feed
+  pub fn includes(&self, feed: &Feed, entry: &FeedEntry) -> bool {
+    !(self.unread_only && entry.read) && entry_matches(&self.filter, feed, entry)
+  }
+}
+
+/// Parsed form of a query feed's `query`
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryFilter {
+  /// `*`, every entry of every feed
+  All,
+  /// `tags:a,b`, entries of feeds tagged with any of the tags
+  Tags(Vec<String>),
+  /// `unread`, entries not read yet
+  Unread,
+  /// `title:word`, entries whose title contains the word, ignoring case
+  Title(String),
+  /// `after:2024-01-01` and `before:2024-06-01`. Entries published at or after `after`
+  /// and strictly before `before` match, undated entries never do.
+  DateRange {
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+  },
+  /// `a AND b`, binds tighter than `OR`
+  And(Box<QueryFilter>, Box<QueryFilter>),
+  /// `a OR b`
+  Or(Box<QueryFilter>, Box<QueryFilter>),
+}
+
+/// Parses clauses joined by `AND` and `OR`, where `AND` takes precedence
+pub fn parse_query(query: &str) -> Result<QueryFilter, String> {
+  let words: Vec<&str> = query.split_whitespace().collect();
+  let mut alternatives = Vec::new();
+  for alternative in words.split(|w| *w == "OR") {
+    let mut clauses = Vec::new();
+    for clause in alternative.split(|w| *w == "AND") {
+      clauses.push(parse_clause(&clause.join(" "))?);
+    }
+    alternatives.push(fold(clauses, QueryFilter::And));
+  }
+  Ok(fold(alternatives, QueryFilter::Or))
+}
+
+/// Chains filters left to right with `join`, `filters` is never empty
+fn fold(
+  filters: Vec<QueryFilter>,
+  join: fn(Box<QueryFilter>, Box<QueryFilter>) -> QueryFilter,
+) -> QueryFilter {
+  let mut filters = filters.into_iter();
+  let first = filters.next().unwrap_or(QueryFilter::All);
+  filters.fold(first, |left, right| join(Box::new(left), Box::new(right)))
+}
+
+fn parse_clause(query: &str) -> Result<QueryFilter, String> {
+  if query.is_empty() {
+    return Err("Empty clause next to AND or OR".to_string());
+  }
+  if query == "*" {
+    return Ok(QueryFilter::All);
+  }
+  if query == "unread" {
+    return Ok(QueryFilter::Unread);
+  }
+  let Some((operator, value)) = query.split_once(':') else {
+    return Err(format!(
+      "Expected `*`, `unread` or `operator:value`, got \"{}\"",
+      query
+    ));
+  };
+  let value = value.trim();
+  if value.is_empty() {
+    return Err(format!("Missing value after `{}:`", operator));
+  }
+  match operator.trim() {
+    "tags" => Ok(QueryFilter::Tags(
+      value
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect(),
+    )),
+    "title" => Ok(QueryFilter::Title(value.to_lowercase())),
+    "after" => Ok(QueryFilter::DateRange {
+      after: Some(parse_date(value)?),
+      before: None,
+    }),
+    "before" => Ok(QueryFilter::DateRange {
+      after: None,
+      before: Some(parse_date(value)?),
+    }),
+    other => Err(format!("Unknown query operator `{}`", other)),
+  }
+}
+
+/// Parses a query date, either a plain `YYYY-MM-DD` day starting at midnight UTC or RFC 3339
+fn parse_date(value: &str) -> Result<DateTime<Utc>, String> {
+  if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+    return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+  }
+  DateTime::parse_from_rfc3339(value)
+    .map(|date| date.with_timezone(&Utc))
+    .map_err(|_| format!("Invalid date \"{}\", expected YYYY-MM-DD", value))
+}
+
+/// Publication date of an entry, falling back to parsing the stored date string
+pub fn published(entry: &FeedEntry) -> Option<DateTime<Utc>> {
+  entry.published_at.or_else(|| {
+    let published = entry.published.as_deref()?;
+    DateTime::parse_from_rfc3339(published)
+      .or_else(|_| DateTime::parse_from_rfc2822(published))
+      .map(|date| date.with_timezone(&Utc))
+      .ok()
+      .or_else(|| published.parse::<DateTime<Utc>>().ok())
+      .or_else(|| {
+        NaiveDate::parse_from_str(published, "%Y-%m-%d")
+          .ok()
+          .and_then(|date| date.and_hms_opt(0, 0, 0))
+          .map(|date| date.and_utc())
+      })
+  })
+}
+
+/// Whether the entries of `feed` can match the filter at all, entry level clauses
+/// are assumed to match. Muted feeds are left out of every query.
+pub fn feed_matches(filter: &QueryFilter, feed: &Feed) -> bool {
+  !feed.muted && clause_matches(filter, feed)
+}
+
+fn clause_matches(filter: &QueryFilter, feed: &Feed) -> bool {
+  match filter {
+    QueryFilter::All
+    | QueryFilter::Unread
+    | QueryFilter::Title(_)
+    | QueryFilter::DateRange { .. } => true,
+    QueryFilter::Tags(tags) => feed
+      .tags
+      .as_ref()
+      .is_some_and(|feed_tags| feed_tags.iter().any(|t| tags.contains(t))),
+    QueryFilter::And(left, right) => clause_matches(left, feed) && clause_matches(right, feed),
+    QueryFilter::Or(left, right) => clause_matches(left, feed) || clause_matches(right, feed),
+  }
+}
+
+/// Whether an entry of `feed` belongs to the query
+pub fn entry_matches(filter: &QueryFilter, feed: &Feed, entry: &FeedEntry) -> bool {
+  match filter {
+    QueryFilter::All => true,
+    QueryFilter::Tags(_) => feed_matches(filter, feed),
+    QueryFilter::Unread => feed_matches(filter, feed) && !entry.read,
+    QueryFilter::Title(word) => entry.title.to_lowercase().contains(word),
+    QueryFilter::DateRange { after, before } => published(entry).is_some_and(|date| {
+      after.is_none_or(|after| date >= after) && before.is_none_or(|before| date < before)
+    }),
+    QueryFilter::And(left, right) => {
+      entry_matches(left, feed, entry) && entry_matches(right, feed, entry)
+    }
+    QueryFilter::Or(left, right) => {
+      entry_matches(left, feed, entry) || entry_matches(right, feed, entry)
+    }
+  }
+}
+
+/// Builds the query feed `name` out of the matching entries of `feeds`, newest first
+pub fn apply_query<'a>(name: &str, query: &Query, feeds: impl Iterator<Item = &'a Feed>) -> Feed {
+  let mut entries: Vec<FeedEntry> = feeds
+    .filter(|f| f.kind == FeedKind::Subscription && feed_matches(&query.filter, f))
+    .flat_map(|f| f.entries.iter().filter(|e| query.includes(f, e)))
+    .cloned()
+    .collect();
+  if query.dedupe {
+    let mut seen = HashSet::new();
+    entries.retain(|e| seen.insert(dedupe_key(e)));
+  }
+  // Stable, so duplicates dropped above don't change which copy is listed
+  sort_entries(&mut entries);
+  Feed {
+    url: String::new(),
+    title: name.to_string(),
+    entries,
+    tags: None,
+    muted: false,
+    position: usize::MAX,
+    validators: Validators::default(),
+    display_max_age_days: None,
+    kind: FeedKind::Query(query.clone()),
+  }
+}
+
+/// Replaces the entries a query feed took from the feeds with these URLs with their
+/// current matches in `feeds`, leaving the entries of other feeds in place
+pub fn update_query<'a>(
+  query_feed: &mut Feed,
+  urls: &[String],
+  feeds: impl Iterator<Item = &'a Feed>,
+) {
+  let FeedKind::Query(query) = &query_feed.kind else {
+    return;
+  };
+  // Which copy of a duplicate wins depends on the order of the feeds, not the fetch
+  if query.dedupe {
+    *query_feed = apply_query(&query_feed.title, &query.clone(), feeds);
+    return;
+  }
+  let matches: Vec<FeedEntry> = feeds
+    .filter(|f| f.kind == FeedKind::Subscription && urls.contains(&f.url))
+    .filter(|f| feed_matches(&query.filter, f))
+    .flat_map(|f| f.entries.iter().filter(|e| query.includes(f, e)))
+    .cloned()
+    .collect();
+  let entries = &mut query_feed.entries;
+  entries.retain(|e| !urls.contains(&e.feed_url));
+  entries.extend(matches);
+  sort_entries(entries);
+}
+
+/// What makes two entries the same article: their link without the scheme, `www.`,
+/// fragment and trailing slash, or their title and date for entries without one
+fn dedupe_key(entry: &FeedEntry) -> String {
+  match entry.links.first() {
+    Some(link) => {
+      let link = link.split('#').next().unwrap_or_default();
+      let link = link
+        .split_once("://")
+        .map_or(link, |(_, rest)| rest)
+        .trim_end_matches('/');
+      let link = link.strip_prefix("www.").unwrap_or(link);
+      // Host names aren't case sensitive, paths may be
+      match link.split_once('/') {
+        Some((host, path)) => format!("{}/{}", host.to_lowercase(), path),
+        None => link.to_lowercase(),
+      }
+    }
+    None => format!(
+      "{}\n{}",
+      entry.title,
+      entry.published.as_deref().unwrap_or_default()
+    ),
+  }
+}
+
+/// Newest first, undated entries sort last
+fn sort_entries(entries: &mut [FeedEntry]) {
+  entries.sort_by_key(|e| std::cmp::Reverse(e.published_at));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(title: &str, published: Option<&str>) -> FeedEntry {
+    FeedEntry {
+      title: title.to_string(),
+      published: published.map(str::to_string),
+      author: None,
+      published_at: None,
+      plain_text: None,
+      links: Vec::new(),
+      enclosures: Vec::new(),
+      read: false,
+      starred: false,
+      feed_url: String::new(),
+    }
+  }
+
+  fn feed(url: &str, tags: &[&str], entries: Vec<FeedEntry>) -> Feed {
+    Feed {
+      url: url.to_string(),
+      title: url.to_string(),
+      entries: entries
+        .into_iter()
+        .map(|e| FeedEntry {
+          feed_url: url.to_string(),
+          ..e
+        })
+        .collect(),
+      tags: (!tags.is_empty()).then(|| tags.iter().map(|t| t.to_string()).collect()),
+      muted: false,
+      position: 0,
+      validators: Validators::default(),
+      display_max_age_days: None,
+      kind: FeedKind::Subscription,
+    }
+  }
+
+  fn query(query: &str) -> Query {
+    Query {
+      filter: parse_query(query).unwrap(),
+      dedupe: false,
+      unread_only: false,
+    }
+  }
+
+  /// Titles of the entries the query takes from `feeds`, sorted
+  fn titles(q: &str, feeds: &[Feed]) -> Vec<String> {
+    let mut titles: Vec<String> = apply_query("Query", &query(q), feeds.iter())
+      .entries
+      .into_iter()
+      .map(|e| e.title)
+      .collect();
+    titles.sort();
+    titles
+  }
+
+  #[test]
+  fn parses_title() {
+    assert_eq!(
+      parse_query("title:Rust").unwrap(),
+      QueryFilter::Title("rust".to_string())
+    );
+    assert!(parse_query("title:").is_err());
+  }
+
+  #[test]
+  fn title_matches_ignoring_case() {
+    let feeds = [feed(
+      "a",
+      &[],
+      vec![
+        entry("Rust 1.80 released", None),
+        entry("TRUSTED builds", None),
+        entry("Go news", None),
+      ],
+    )];
+    assert_eq!(
+      titles("title:rust", &feeds),
+      ["Rust 1.80 released", "TRUSTED builds"]
+    );
+    assert_eq!(titles("title:RUST", &feeds), titles("title:rust", &feeds));
+  }
+
+  #[test]
+  fn title_without_matches_is_empty() {
+    let feeds = [feed("a", &[], vec![entry("Go news", None)])];
+    assert!(titles("title:python", &feeds).is_empty());
+  }
+
+  fn tags(tag: &str) -> Box<QueryFilter> {
+    Box::new(QueryFilter::Tags(vec![tag.to_string()]))
+  }
+
+  #[test]
+  fn and_binds_tighter_than_or() {
+    assert_eq!(
+      parse_query("tags:a OR tags:b AND tags:c").unwrap(),
+      QueryFilter::Or(tags("a"), Box::new(QueryFilter::And(tags("b"), tags("c"))))
+    );
+    assert_eq!(
+      parse_query("tags:a AND tags:b OR tags:c").unwrap(),
+      QueryFilter::Or(Box::new(QueryFilter::And(tags("a"), tags("b"))), tags("c"))
+    );
+  }
+
+  #[test]
+  fn same_operators_chain_left_to_right() {
+    assert_eq!(
+      parse_query("tags:a AND tags:b AND tags:c").unwrap(),
+      QueryFilter::And(Box::new(QueryFilter::And(tags("a"), tags("b"))), tags("c"))
+    );
+  }
+
+  #[test]
+  fn bare_forms_still_parse() {
+    assert_eq!(parse_query("*").unwrap(), QueryFilter::All);
+    assert_eq!(
+      parse_query("tags:blog, news").unwrap(),
+      QueryFilter::Tags(vec!["blog".to_string(), "news".to_string()])
+    );
+  }
+
+  #[test]
+  fn rejects_empty_clauses() {
+    for q in ["tags:a AND", "OR tags:a", "tags:a AND OR tags:b"] {
+      assert!(parse_query(q).is_err(), "{}", q);
+    }
+  }
+
+  /// A tech feed and a news feed, both with a release and another entry
+  fn tech_and_news() -> [Feed; 2] {
+    [
+      feed(
+        "tech",
+        &["tech"],
+        vec![entry("Tech release", None), entry("Tech talk", None)],
+      ),
+      feed(
+        "news",
+        &["news"],
+        vec![entry("News release", None), entry("News story", None)],
+      ),
+    ]
+  }
+
+  #[test]
+  fn and_of_feed_and_entry_predicates() {
+    let feeds = tech_and_news();
+    assert_eq!(
+      titles("tags:tech AND title:release", &feeds),
+      ["Tech release"]
+    );
+  }
+
+  #[test]
+  fn or_of_feed_and_entry_predicates() {
+    let feeds = tech_and_news();
+    assert_eq!(
+      titles("tags:news OR title:release", &feeds),
+      ["News release", "News story", "Tech release"]
+    );
+    // The entry predicate still applies to feeds the tags clause doesn't match
+    assert_eq!(
+      titles(
+        "tags:tech AND title:talk OR tags:news AND title:release",
+        &feeds
+      ),
+      ["News release", "Tech talk"]
+    );
+  }
+
+  #[test]
+  fn after_includes_its_day_and_before_excludes_its_own() {
+    let feeds = [feed(
+      "a",
+      &[],
+      vec![
+        entry("Before the range", Some("2023-12-31T23:59:59Z")),
+        entry("First instant", Some("2024-01-01T00:00:00Z")),
+        entry("Last instant", Some("2024-05-31T23:59:59Z")),
+        entry("End of the range", Some("2024-06-01T00:00:00Z")),
+      ],
+    )];
+    assert_eq!(
+      titles("after:2024-01-01 AND before:2024-06-01", &feeds),
+      ["First instant", "Last instant"]
+    );
+    assert_eq!(titles("after:2024-06-01", &feeds), ["End of the range"]);
+    assert_eq!(titles("before:2024-01-01", &feeds), ["Before the range"]);
+  }
+
+  #[test]
+  fn dates_parse_in_every_stored_format() {
+    let feeds = [feed(
+      "a",
+      &[],
+      vec![
+        entry("RFC 3339", Some("2024-03-01T12:00:00+02:00")),
+        entry("RFC 2822", Some("Fri, 01 Mar 2024 12:00:00 GMT")),
+        entry("Stored", Some("2024-03-01 12:00:00 UTC")),
+        entry("Day", Some("2024-03-01")),
+      ],
+    )];
+    assert_eq!(
+      titles("after:2024-03-01 AND before:2024-03-02", &feeds),
+      ["Day", "RFC 2822", "RFC 3339", "Stored"]
+    );
+  }
+
+  #[test]
+  fn undated_entries_never_match_dates() {
+    let feeds = [feed(
+      "a",
+      &[],
+      vec![
+        entry("Undated", None),
+        entry("Garbled", Some("last Tuesday")),
+      ],
+    )];
+    assert!(titles("after:1970-01-01", &feeds).is_empty());
+    assert!(titles("before:2100-01-01", &feeds).is_empty());
+    assert_eq!(titles("*", &feeds).len(), 2);
+  }
+
+  #[test]
+  fn rejects_invalid_dates() {
+    assert!(parse_query("after:2024-13-01").is_err());
+    assert!(parse_query("before:yesterday").is_err());
+  }
+
+  #[test]
+  fn muted_feeds_are_left_out_of_every_query() {
+    let mut muted = feed("muted", &["tech"], vec![entry("Muted release", None)]);
+    muted.muted = true;
+    let feeds = [
+      feed("a", &["tech"], vec![entry("Listed release", None)]),
+      muted,
+    ];
+    for q in ["*", "unread", "tags:tech", "title:release"] {
+      assert_eq!(titles(q, &feeds), ["Listed release"], "{}", q);
+    }
+    // Updating the query feed after a fetch of the muted feed doesn't add it either
+    let mut query_feed = apply_query("Query", &query("*"), feeds.iter());
+    update_query(&mut query_feed, &["muted".to_string()], feeds.iter());
+    assert_eq!(query_feed.entries.len(), 1);
+  }
+
+  fn linked(title: &str, link: &str) -> FeedEntry {
+    FeedEntry {
+      links: vec![link.to_string()],
+      ..entry(title, None)
+    }
+  }
+
+  #[test]
+  fn dedupe_keeps_the_first_copy_of_an_article() {
+    let feeds = [
+      feed(
+        "first",
+        &[],
+        vec![
+          linked("Shared", "https://www.Example.com/post/"),
+          entry("Unlinked", Some("2024-05-01")),
+        ],
+      ),
+      feed(
+        "second",
+        &[],
+        vec![
+          linked("Shared again", "http://example.com/post#comments"),
+          entry("Unlinked", Some("2024-05-01")),
+          linked("Other path", "https://example.com/Post"),
+        ],
+      ),
+    ];
+    let deduped = apply_query(
+      "Query",
+      &Query {
+        dedupe: true,
+        ..query("*")
+      },
+      feeds.iter(),
+    );
+    let kept: Vec<(&str, &str)> = deduped
+      .entries
+      .iter()
+      .map(|e| (e.title.as_str(), e.feed_url.as_str()))
+      .collect();
+    assert_eq!(
+      kept,
+      [
+        ("Shared", "first"),
+        ("Unlinked", "first"),
+        ("Other path", "second")
+      ]
+    );
+    assert_eq!(titles("*", &feeds).len(), 5);
+  }
+}
@@ -0,0 +1,103 @@
+use crate::config::SaveFormat;
+use crate::feeds::{Feed, FeedEntry};
+use std::{
+  fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+/// Writes an entry to a new file in `dir` and returns its path. The name is built from
+/// the slugified title and publication date, existing files get a numeric suffix.
+pub fn save_entry(
+  feed: &Feed,
+  entry: &FeedEntry,
+  dir: &Path,
+  format: &SaveFormat,
+) -> io::Result<PathBuf> {
+  fs::create_dir_all(dir)?;
+  let date = entry.published_at.map(|d| d.format("%Y-%m-%d").to_string());
+  let stem = match date {
+    Some(date) => format!("{}-{}", slugify(&entry.title), date),
+    None => slugify(&entry.title),
+  };
+  let extension = match format {
+    SaveFormat::Text => "txt",
+    SaveFormat::Markdown => "md",
+  };
+
+  let mut suffix = 0;
+  loop {
+    let name = match suffix {
+      0 => format!("{}.{}", stem, extension),
+      n => format!("{}-{}.{}", stem, n, extension),
+    };
+    let path = dir.join(name);
+    // `create_new` fails on existing files, so nothing gets overwritten
+    match fs::OpenOptions::new()
+      .write(true)
+      .create_new(true)
+      .open(&path)
+    {
+      Ok(mut file) => {
+        file.write_all(entry_contents(feed, entry, format).as_bytes())?;
+        return Ok(path);
+      }
+      Err(e) if e.kind() == io::ErrorKind::AlreadyExists => suffix += 1,
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+fn entry_contents(feed: &Feed, entry: &FeedEntry, format: &SaveFormat) -> String {
+  let published = entry.published.as_deref().unwrap_or("Unknown");
+  match format {
+    SaveFormat::Text => {
+      let mut text = format!(
+        "Title: {}\nFeed: {}\nPublished: {}\n",
+        entry.title, feed.title, published
+      );
+      if let Some(author) = &entry.author {
+        text.push_str(&format!("Author: {}\n", author));
+      }
+      if !entry.links.is_empty() {
+        text.push_str(&format!("Link: {}\n", entry.links.join(", ")));
+      }
+      text.push('\n');
+      text.push_str(entry.plain_text.as_deref().unwrap_or_default());
+      text.push('\n');
+      text
+    }
+    SaveFormat::Markdown => {
+      let mut text = format!(
+        "# {}\n\n- Feed: {}\n- Published: {}\n",
+        entry.title, feed.title, published
+      );
+      if let Some(author) = &entry.author {
+        text.push_str(&format!("- Author: {}\n", author));
+      }
+      for link in &entry.links {
+        text.push_str(&format!("- Link: <{}>\n", link));
+      }
+      text.push('\n');
+      text.push_str(entry.plain_text.as_deref().unwrap_or_default());
+      text.push('\n');
+      text
+    }
+  }
+}
+
+/// Lowercases the title and joins its alphanumeric runs with dashes
+fn slugify(title: &str) -> String {
+  let slug = title
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|part| !part.is_empty())
+    .map(str::to_lowercase)
+    .collect::<Vec<_>>()
+    .join("-");
+  if slug.is_empty() {
+    "entry".to_string()
+  } else {
+    // Keep file names reasonably short
+    slug.chars().take(80).collect()
+  }
+}
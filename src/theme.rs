@@ -0,0 +1,144 @@
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// Colors the interface is drawn with
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+  pub border: Color,
+  /// App title, counts and key names
+  pub title: Color,
+  /// Pane and popup titles
+  pub label: Color,
+  /// Title line of the entry view
+  pub heading: Color,
+  /// Feed line of the entry view and the typed entry number
+  pub accent: Color,
+  pub link: Color,
+  /// Selected row of the focused list
+  pub highlight_fg: Color,
+  pub highlight_bg: Color,
+  pub read: Color,
+  pub unread: Color,
+  /// Muted feeds and placeholder text
+  pub muted: Color,
+  pub error: Color,
+}
+
+impl Theme {
+  pub const DEFAULT: Theme = Theme {
+    border: Color::Blue,
+    title: Color::Yellow,
+    label: Color::Green,
+    heading: Color::Magenta,
+    accent: Color::Cyan,
+    link: Color::Blue,
+    highlight_fg: Color::Black,
+    highlight_bg: Color::Yellow,
+    read: Color::Reset,
+    unread: Color::Reset,
+    muted: Color::DarkGray,
+    error: Color::Red,
+  };
+
+  pub const MONOCHROME: Theme = Theme {
+    border: Color::Reset,
+    title: Color::Reset,
+    label: Color::Reset,
+    heading: Color::Reset,
+    accent: Color::Reset,
+    link: Color::Reset,
+    highlight_fg: Color::Black,
+    highlight_bg: Color::White,
+    read: Color::DarkGray,
+    unread: Color::Reset,
+    muted: Color::DarkGray,
+    error: Color::Reset,
+  };
+
+  pub const SOLARIZED: Theme = Theme {
+    border: Color::Rgb(0x26, 0x8b, 0xd2),
+    title: Color::Rgb(0xb5, 0x89, 0x00),
+    label: Color::Rgb(0x85, 0x99, 0x00),
+    heading: Color::Rgb(0xd3, 0x36, 0x82),
+    accent: Color::Rgb(0x2a, 0xa1, 0x98),
+    link: Color::Rgb(0x6c, 0x71, 0xc4),
+    highlight_fg: Color::Rgb(0x07, 0x36, 0x42),
+    highlight_bg: Color::Rgb(0xb5, 0x89, 0x00),
+    read: Color::Rgb(0x58, 0x6e, 0x75),
+    unread: Color::Rgb(0x83, 0x94, 0x96),
+    muted: Color::Rgb(0x58, 0x6e, 0x75),
+    error: Color::Rgb(0xdc, 0x32, 0x2f),
+  };
+
+  fn preset(name: &str) -> Option<Theme> {
+    match name {
+      "default" => Some(Theme::DEFAULT),
+      "monochrome" => Some(Theme::MONOCHROME),
+      "solarized" => Some(Theme::SOLARIZED),
+      _ => None,
+    }
+  }
+}
+
+/// The `[theme]` table, a preset with single colors overridden by name.
+/// Colors are one of the 16 terminal color names, like "blue" or "light-red",
+/// or `#rrggbb`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+  pub preset: Option<String>,
+  pub border: Option<String>,
+  pub title: Option<String>,
+  pub label: Option<String>,
+  pub heading: Option<String>,
+  pub accent: Option<String>,
+  pub link: Option<String>,
+  pub highlight_fg: Option<String>,
+  pub highlight_bg: Option<String>,
+  pub read: Option<String>,
+  pub unread: Option<String>,
+  pub muted: Option<String>,
+  pub error: Option<String>,
+}
+
+impl ThemeConfig {
+  /// Builds the theme, failing on unknown presets or colors
+  pub fn resolve(&self) -> Result<Theme, String> {
+    let mut theme = match &self.preset {
+      Some(name) => Theme::preset(name).ok_or_else(|| {
+        format!(
+          "Unknown theme preset \"{}\", expected default, monochrome or solarized",
+          name
+        )
+      })?,
+      None => Theme::DEFAULT,
+    };
+    let overrides = [
+      (&self.border, &mut theme.border),
+      (&self.title, &mut theme.title),
+      (&self.label, &mut theme.label),
+      (&self.heading, &mut theme.heading),
+      (&self.accent, &mut theme.accent),
+      (&self.link, &mut theme.link),
+      (&self.highlight_fg, &mut theme.highlight_fg),
+      (&self.highlight_bg, &mut theme.highlight_bg),
+      (&self.read, &mut theme.read),
+      (&self.unread, &mut theme.unread),
+      (&self.muted, &mut theme.muted),
+      (&self.error, &mut theme.error),
+    ];
+    for (value, color) in overrides {
+      if let Some(value) = value {
+        *color = parse_color(value)?;
+      }
+    }
+    Ok(theme)
+  }
+}
+
+/// Parses a terminal color name or `#rrggbb`
+fn parse_color(value: &str) -> Result<Color, String> {
+  value
+    .parse::<Color>()
+    .map_err(|_| format!("Invalid color \"{}\" in [theme]", value))
+}
@@ -1,21 +1,46 @@
 use std::io::{self, stdout, Stdout};
 
-use crossterm::{execute, terminal::*};
+use crossterm::{
+  event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
+  execute,
+  style::Print,
+  terminal::*,
+};
 use ratatui::prelude::*;
 
 /// A type alias for the terminal type used in this application
 pub type Tui = Terminal<CrosstermBackend<Stdout>>;
 
-/// Initialize the terminal
-pub fn init() -> io::Result<Tui> {
-  execute!(stdout(), EnterAlternateScreen)?;
+/// Saves the terminal title on terminals that keep a stack of them
+const PUSH_TITLE: &str = "\x1b[22;0t";
+const POP_TITLE: &str = "\x1b[23;0t";
+
+/// Initialize the terminal, reporting mouse events if `mouse` is set and saving the
+/// title to restore if the app sets it (`title`)
+pub fn init(mouse: bool, title: bool) -> io::Result<Tui> {
+  execute!(stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
+  if mouse {
+    execute!(stdout(), EnableMouseCapture)?;
+  }
+  if title {
+    execute!(stdout(), Print(PUSH_TITLE))?;
+  }
   enable_raw_mode()?;
   Terminal::new(CrosstermBackend::new(stdout()))
 }
 
-/// Restore the terminal to its original state
-pub fn restore() -> io::Result<()> {
-  execute!(stdout(), LeaveAlternateScreen)?;
+/// Restore the terminal to its original state. A `title` set by the app is cleared
+/// where the terminal doesn't bring back the one saved in `init`.
+pub fn restore(title: bool) -> io::Result<()> {
+  execute!(
+    stdout(),
+    DisableMouseCapture,
+    DisableBracketedPaste,
+    LeaveAlternateScreen
+  )?;
+  if title {
+    execute!(stdout(), SetTitle(""), Print(POP_TITLE))?;
+  }
   disable_raw_mode()?;
   Ok(())
 }